@@ -93,8 +93,10 @@ void loop() {
   if (Serial.available()) {
     String i = Serial.readStringUntil('\n');
     if(i.startsWith("*IDN?")) {
-      Serial.print("supply, 100MQ, " SERIAL_NUMBER);
+      Serial.print("THURLBY THANDAR, MX100QP, " SERIAL_NUMBER);
       Serial.println(", x");
+    } else if(i.startsWith("*ESR?") || i.startsWith("*STB?") || i.startsWith("EER?") || i.startsWith("QER?")) {
+      Serial.println(0);
     } else if(i.startsWith("OPALL")) {
       if(i[6] == '1') {
         for(int ch = 0; ch < CHANNELS; ch++) {
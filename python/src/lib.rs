@@ -0,0 +1,267 @@
+//! Python bindings of the MX100QP driver for test automation.
+//!
+//! The async driver runs on a tokio runtime owned by each opened supply,
+//! every method blocks until the device answers. Channels are numbered from 1.
+
+use std::{path::PathBuf, sync::Mutex};
+
+use power_supply_control::{
+    config::{AppConfig, Connection, PowerSupplyConfig},
+    get_config_dir,
+    mx100qp::{
+        Channel, MultiChannelOff, MultiChannelOn, Mx100qp as Driver, ProtocolError,
+        VoltageTrackingState,
+    },
+};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use tokio::runtime::Runtime;
+
+create_exception!(mx100qp, Mx100qpError, PyException);
+
+fn error(err: impl std::fmt::Display) -> PyErr {
+    Mx100qpError::new_err(err.to_string())
+}
+
+struct Inner {
+    runtime: Runtime,
+    port: Driver,
+}
+
+/// Opened power supply.
+#[pyclass(module = "mx100qp")]
+struct Mx100qp {
+    inner: Mutex<Inner>,
+    id: String,
+    model: &'static str,
+    channels: u8,
+}
+
+impl Mx100qp {
+    fn connect(py: Python<'_>, config: PowerSupplyConfig) -> PyResult<Self> {
+        py.detach(|| {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(error)?;
+            let port = runtime.block_on(Driver::open(&config)).map_err(error)?;
+            Ok(Self {
+                id: config.id,
+                model: port.model.name,
+                channels: port.model.channels,
+                inner: Mutex::new(Inner { runtime, port }),
+            })
+        })
+    }
+
+    /// Runs a driver call without holding the GIL.
+    fn run<T, F>(&self, py: Python<'_>, call: F) -> PyResult<T>
+    where
+        T: Send,
+        F: AsyncFnOnce(&mut Driver) -> Result<T, ProtocolError> + Send,
+    {
+        py.detach(|| {
+            let mut inner = self.inner.lock().unwrap();
+            let Inner { runtime, port } = &mut *inner;
+            runtime.block_on(call(port)).map_err(error)
+        })
+    }
+
+    /// Converts the channel number starting from 1 into its index.
+    fn index(&self, channel: u8) -> PyResult<u8> {
+        if channel == 0 || channel > self.channels {
+            return Err(PyValueError::new_err(format!(
+                "{} has channels 1 to {}, got {channel}",
+                self.model, self.channels
+            )));
+        }
+        Ok(channel - 1)
+    }
+
+    fn read_channel(&self, py: Python<'_>, channel: u8) -> PyResult<Channel> {
+        let index = self.index(channel)?;
+        let mut channels = self.run(py, async |port| port.read_channels().await)?;
+        Ok(channels.swap_remove(index as usize))
+    }
+}
+
+fn channel_dict<'py>(py: Python<'py>, channel: &Channel) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("channel", channel.index + 1)?;
+    dict.set_item("enabled", channel.enabled)?;
+    dict.set_item("voltage", channel.voltage.current)?;
+    dict.set_item("voltage_set", channel.voltage.set)?;
+    dict.set_item("current", channel.current.current)?;
+    dict.set_item("current_set", channel.current.set)?;
+    dict.set_item("vrange", channel.vrange)?;
+    dict.set_item("ovp", channel.overvoltage_trip)?;
+    dict.set_item("ocp", channel.overcurrent_trip)?;
+    let tracking = match channel.voltage_tracking {
+        VoltageTrackingState::None => "none",
+        VoltageTrackingState::Master => "master",
+        VoltageTrackingState::Slave => "slave",
+    };
+    dict.set_item("tracking", tracking)?;
+    let status: Vec<&str> = channel.status.iter_names().map(|(name, _)| name).collect();
+    dict.set_item("status", status)?;
+    Ok(dict)
+}
+
+#[pymethods]
+impl Mx100qp {
+    /// Opens a supply from `config.json` by its name or id, the GUI configuration is used by default.
+    #[staticmethod]
+    #[pyo3(signature = (supply, config = None))]
+    fn open(py: Python<'_>, supply: &str, config: Option<PathBuf>) -> PyResult<Self> {
+        let path = config.unwrap_or_else(|| get_config_dir().join("config.json"));
+        let appconfig = AppConfig::load_from_file(path)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        let config = appconfig
+            .find_power_supply(supply)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown power supply {supply}")))?;
+        Self::connect(py, config.clone())
+    }
+
+    /// Opens a supply on a serial port, `id` is the serial number reported by the device.
+    #[staticmethod]
+    fn open_port(py: Python<'_>, port: String, id: String) -> PyResult<Self> {
+        let connection = Connection::Serial {
+            serial_port: port.clone(),
+        };
+        Self::connect(py, PowerSupplyConfig::new(connection, id, port))
+    }
+
+    #[getter]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[getter]
+    fn model(&self) -> &str {
+        self.model
+    }
+
+    #[getter]
+    fn channels(&self) -> u8 {
+        self.channels
+    }
+
+    fn set_voltage(&self, py: Python<'_>, channel: u8, voltage: f32) -> PyResult<()> {
+        let ch = self.index(channel)?;
+        self.run(py, async |port| port.set_voltage(ch, voltage).await)
+    }
+
+    fn set_current(&self, py: Python<'_>, channel: u8, current: f32) -> PyResult<()> {
+        let ch = self.index(channel)?;
+        self.run(py, async |port| port.set_current(ch, current).await)
+    }
+
+    /// Measured output voltage.
+    fn get_voltage(&self, py: Python<'_>, channel: u8) -> PyResult<f32> {
+        Ok(self.read_channel(py, channel)?.voltage.current)
+    }
+
+    /// Measured output current.
+    fn get_current(&self, py: Python<'_>, channel: u8) -> PyResult<f32> {
+        Ok(self.read_channel(py, channel)?.current.current)
+    }
+
+    /// Switches the channel on, or all channels with multi-on if none is given.
+    #[pyo3(signature = (channel = None))]
+    fn on(&self, py: Python<'_>, channel: Option<u8>) -> PyResult<()> {
+        match channel {
+            Some(channel) => {
+                let ch = self.index(channel)?;
+                self.run(py, async |port| port.channel_on(ch).await)
+            }
+            None => self.run(py, async |port| port.all_channel_on().await),
+        }
+    }
+
+    /// Switches the channel off, or all channels if none is given.
+    #[pyo3(signature = (channel = None))]
+    fn off(&self, py: Python<'_>, channel: Option<u8>) -> PyResult<()> {
+        match channel {
+            Some(channel) => {
+                let ch = self.index(channel)?;
+                self.run(py, async |port| port.channel_off(ch).await)
+            }
+            None => self.run(py, async |port| port.all_channel_off().await),
+        }
+    }
+
+    /// Sets the over-voltage trip, `None` disables it.
+    #[pyo3(signature = (channel, voltage))]
+    fn set_ovp(&self, py: Python<'_>, channel: u8, voltage: Option<f32>) -> PyResult<()> {
+        let ch = self.index(channel)?;
+        self.run(py, async |port| {
+            port.set_overvoltage_trip(ch, voltage).await
+        })
+    }
+
+    /// Sets the over-current trip, `None` disables it.
+    #[pyo3(signature = (channel, current))]
+    fn set_ocp(&self, py: Python<'_>, channel: u8, current: Option<f32>) -> PyResult<()> {
+        let ch = self.index(channel)?;
+        self.run(py, async |port| {
+            port.set_overcurrent_trip(ch, current).await
+        })
+    }
+
+    fn trip_reset(&self, py: Python<'_>) -> PyResult<()> {
+        self.run(py, async |port| port.trip_reset().await)
+    }
+
+    /// Delay of the channel when all channels are switched on, `None` leaves the channel off.
+    #[pyo3(signature = (channel, delay_ms))]
+    fn set_multi_on(&self, py: Python<'_>, channel: u8, delay_ms: Option<u32>) -> PyResult<()> {
+        let ch = self.index(channel)?;
+        let behaviour = delay_ms.map_or(MultiChannelOn::Disabled, MultiChannelOn::Delay);
+        self.run(py, async |port| {
+            port.multichannel_on_setup(ch, behaviour).await
+        })
+    }
+
+    /// Delay of the channel when all channels are switched off, `None` leaves the channel on.
+    #[pyo3(signature = (channel, delay_ms))]
+    fn set_multi_off(&self, py: Python<'_>, channel: u8, delay_ms: Option<u32>) -> PyResult<()> {
+        let ch = self.index(channel)?;
+        let behaviour = delay_ms.map_or(MultiChannelOff::Disabled, MultiChannelOff::Delay);
+        self.run(py, async |port| {
+            port.multichannel_off_setup(ch, behaviour).await
+        })
+    }
+
+    /// Selects the voltage tracking mode by its `CONFIG` value.
+    fn set_voltage_tracking(&self, py: Python<'_>, config: u8) -> PyResult<()> {
+        self.run(py, async |port| port.set_voltage_tracking(config).await)
+    }
+
+    fn get_voltage_tracking(&self, py: Python<'_>) -> PyResult<u8> {
+        self.run(py, async |port| port.get_voltage_tracking().await)
+    }
+
+    /// Reads settings and readings of all channels as dicts.
+    fn read_channels<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let channels = self.run(py, async |port| port.read_channels().await)?;
+        channels
+            .iter()
+            .map(|channel| channel_dict(py, channel))
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Mx100qp {} {}>", self.model, self.id)
+    }
+}
+
+#[pymodule]
+fn mx100qp(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Mx100qp>()?;
+    m.add("Mx100qpError", m.py().get_type::<Mx100qpError>())?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+import json
+
+import pytest
+
+from mx100qp import Mx100qp, Mx100qpError
+
+
+@pytest.fixture
+def psu(tmp_path):
+    config = tmp_path / "config.json"
+    config.write_text(json.dumps({
+        "power_supplies": [{
+            "simulator": "SIM",
+            "id": "SIM",
+            "name": "bench",
+            "channels": [],
+        }],
+    }))
+    return Mx100qp.open("bench", str(config))
+
+
+def test_open(psu):
+    assert psu.id == "SIM"
+    assert psu.model == "MX100QP"
+    assert psu.channels == 4
+
+
+def test_unknown_supply(tmp_path):
+    with pytest.raises(ValueError):
+        Mx100qp.open("missing", str(tmp_path / "config.json"))
+
+
+def test_invalid_config(tmp_path):
+    config = tmp_path / "config.json"
+    config.write_text("{")
+    with pytest.raises(ValueError):
+        Mx100qp.open("bench", str(config))
+
+
+def test_output(psu):
+    psu.set_voltage(1, 5.0)
+    psu.set_current(1, 0.5)
+    psu.set_ovp(1, 6.0)
+    psu.set_ocp(1, None)
+    psu.on(1)
+
+    channel = psu.read_channels()[0]
+    assert channel["channel"] == 1
+    assert channel["enabled"]
+    assert channel["voltage_set"] == 5.0
+    assert channel["current_set"] == 0.5
+    assert channel["ovp"] == 6.0
+    assert channel["ocp"] is None
+    assert psu.get_voltage(1) == pytest.approx(5.0)
+
+    psu.off()
+    assert not any(channel["enabled"] for channel in psu.read_channels())
+
+
+def test_channel_range(psu):
+    with pytest.raises(ValueError):
+        psu.on(5)
+
+
+def test_voltage_tracking(psu):
+    psu.set_voltage_tracking(0)
+    assert psu.get_voltage_tracking() == 0
+    with pytest.raises(Mx100qpError):
+        psu.set_voltage_tracking(42)
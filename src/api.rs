@@ -0,0 +1,497 @@
+//! Local HTTP API exposing the state of the supplies and routing actions into their coroutines.
+
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    Json, Router,
+    extract::{
+        Path, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use log::{error, info, warn};
+use power_supply_control::{
+    config::ApiConfig,
+    mx100qp::{MultiChannelOff, MultiChannelOn},
+};
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tokio::{net::TcpListener, sync::broadcast::error::RecvError};
+
+use crate::{
+    components::power_supply::{ChannelSelection, PowerSupplyAction},
+    supplies::{Event, Frame, Supplies, SupplyState, find_supply},
+};
+
+/// Operation of `PowerSupplyAction` with channels addressed by name or number.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ApiAction {
+    On {
+        channel: Option<String>,
+    },
+    Off {
+        channel: Option<String>,
+    },
+    SetVoltage {
+        channel: String,
+        voltage: f32,
+    },
+    SetCurrent {
+        channel: String,
+        current: f32,
+    },
+    RenameChannel {
+        channel: String,
+        name: String,
+    },
+    /// Delayed multi-on, `null` delay disables the channel in multi-on.
+    SetMultiOn {
+        channel: String,
+        delay_ms: Option<u32>,
+    },
+    /// Delayed multi-off, `null` delay leaves the channel on in multi-off.
+    SetMultiOff {
+        channel: String,
+        delay_ms: Option<u32>,
+    },
+    SetVrange {
+        channel: String,
+        vrange: u8,
+    },
+    SetAutoVrange {
+        channel: String,
+        enabled: bool,
+    },
+    SetVoltageTracking {
+        config: u8,
+    },
+    /// `null` limit disables the trip.
+    SetOvervoltageTrip {
+        channel: String,
+        limit: Option<f32>,
+    },
+    SetOvercurrentTrip {
+        channel: String,
+        limit: Option<f32>,
+    },
+    Reconfigure,
+    TripReset,
+    SaveSetup {
+        channel: Option<String>,
+        store: u8,
+        label: String,
+    },
+    RecallSetup {
+        channel: Option<String>,
+        store: u8,
+    },
+}
+
+impl ApiAction {
+    fn into_action(self, supply: &SupplyState) -> Result<PowerSupplyAction, ApiError> {
+        let channel = |name: &str| {
+            supply.find_channel(name).ok_or_else(|| {
+                ApiError(
+                    StatusCode::NOT_FOUND,
+                    format!("Unknown channel {name} of {}", supply.name),
+                )
+            })
+        };
+        let selection = |name: Option<String>| match name {
+            Some(name) => channel(&name).map(ChannelSelection::Channel),
+            None => Ok(ChannelSelection::AllChannels),
+        };
+
+        Ok(match self {
+            ApiAction::On { channel } => PowerSupplyAction::On(selection(channel)?),
+            ApiAction::Off { channel } => {
+                PowerSupplyAction::Off(selection(channel)?, Instant::now())
+            }
+            ApiAction::SetVoltage {
+                channel: ch,
+                voltage,
+            } => PowerSupplyAction::SetVoltage(channel(&ch)?, voltage),
+            ApiAction::SetCurrent {
+                channel: ch,
+                current,
+            } => PowerSupplyAction::SetCurrent(channel(&ch)?, current),
+            ApiAction::RenameChannel { channel: ch, name } => {
+                PowerSupplyAction::RenameChannel(channel(&ch)?, name)
+            }
+            ApiAction::SetMultiOn {
+                channel: ch,
+                delay_ms,
+            } => PowerSupplyAction::SetMultiChannel(
+                channel(&ch)?,
+                delay_ms.map_or(MultiChannelOn::Disabled, MultiChannelOn::Delay),
+            ),
+            ApiAction::SetMultiOff {
+                channel: ch,
+                delay_ms,
+            } => PowerSupplyAction::SetMultiChannelOff(
+                channel(&ch)?,
+                delay_ms.map_or(MultiChannelOff::Disabled, MultiChannelOff::Delay),
+            ),
+            ApiAction::SetVrange {
+                channel: ch,
+                vrange,
+            } => PowerSupplyAction::SetVRange(channel(&ch)?, vrange),
+            ApiAction::SetAutoVrange {
+                channel: ch,
+                enabled,
+            } => PowerSupplyAction::SetAutoVRange(channel(&ch)?, enabled),
+            ApiAction::SetVoltageTracking { config } => {
+                PowerSupplyAction::SetVoltageTracking(config)
+            }
+            ApiAction::SetOvervoltageTrip { channel: ch, limit } => {
+                PowerSupplyAction::SetOvervoltageTrip(channel(&ch)?, limit)
+            }
+            ApiAction::SetOvercurrentTrip { channel: ch, limit } => {
+                PowerSupplyAction::SetOvercurrentTrip(channel(&ch)?, limit)
+            }
+            ApiAction::Reconfigure => PowerSupplyAction::Reconfigure,
+            ApiAction::TripReset => PowerSupplyAction::TripReset,
+            ApiAction::SaveSetup {
+                channel,
+                store,
+                label,
+            } => PowerSupplyAction::SaveSetup(selection(channel)?, store, label),
+            ApiAction::RecallSetup { channel, store } => {
+                PowerSupplyAction::RecallSetup(selection(channel)?, store)
+            }
+        })
+    }
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+#[derive(Clone)]
+struct Api {
+    supplies: Supplies,
+    token: Option<Arc<str>>,
+}
+
+impl Api {
+    /// Checks the `Authorization: Bearer <token>` header of requests changing the supplies.
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), ApiError> {
+        let Some(token) = &self.token else {
+            return Err(ApiError(
+                StatusCode::FORBIDDEN,
+                "Actions are disabled without a token in the configuration".to_string(),
+            ));
+        };
+
+        let provided = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match provided {
+            // constant-time, the comparison must not reveal how much of the token matched
+            Some(provided) if bool::from(provided.as_bytes().ct_eq(token.as_bytes())) => Ok(()),
+            _ => Err(ApiError(
+                StatusCode::UNAUTHORIZED,
+                "Invalid token".to_string(),
+            )),
+        }
+    }
+}
+
+fn unknown_supply(name: &str) -> ApiError {
+    ApiError(
+        StatusCode::NOT_FOUND,
+        format!("Unknown power supply {name}"),
+    )
+}
+
+async fn list(State(api): State<Api>) -> Json<Vec<SupplyState>> {
+    let supplies = api.supplies.lock();
+    Json(supplies.iter().map(|supply| supply.state.clone()).collect())
+}
+
+async fn show(
+    State(api): State<Api>,
+    Path(name): Path<String>,
+) -> Result<Json<SupplyState>, ApiError> {
+    let supplies = api.supplies.lock();
+    let supply = find_supply(&supplies, &name).ok_or_else(|| unknown_supply(&name))?;
+    Ok(Json(supply.state.clone()))
+}
+
+/// Queues the action into the coroutine of the supply, the result shows up in its state.
+async fn action(
+    State(api): State<Api>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(action): Json<ApiAction>,
+) -> Result<StatusCode, ApiError> {
+    api.authorize(&headers)?;
+
+    let supplies = api.supplies.lock();
+    let supply = find_supply(&supplies, &name).ok_or_else(|| unknown_supply(&name))?;
+    if !supply.state.connected {
+        return Err(ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{} is not connected", supply.state.name),
+        ));
+    }
+
+    let action = action.into_action(&supply.state)?;
+    info!("API: {action:?}");
+    supply.actions.unbounded_send(action).map_err(|_| {
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{} is not running", supply.state.name),
+        )
+    })?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Streams telemetry frames as JSON text messages, starting with the last readings.
+async fn stream_telemetry(mut socket: WebSocket, supplies: Supplies) {
+    let mut events = supplies.subscribe();
+
+    let last: Vec<Frame> = supplies
+        .lock()
+        .iter()
+        .filter(|supply| supply.state.connected)
+        .map(|supply| {
+            let channels = supply.state.channels.clone();
+            Frame::new(
+                &supply.state.id,
+                &supply.state.name,
+                Event::Readings {
+                    channels,
+                    started: None,
+                },
+            )
+        })
+        .collect();
+    for frame in last {
+        if send_frame(&mut socket, &frame).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        let frame = match events.recv().await {
+            Ok(frame) => frame,
+            Err(RecvError::Lagged(dropped)) => {
+                warn!("Telemetry subscriber lagged, {dropped} frames dropped");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if send_frame(&mut socket, &frame).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn send_frame(socket: &mut WebSocket, frame: &Frame) -> Result<(), axum::Error> {
+    let json = serde_json::to_string(frame).unwrap();
+    socket.send(Message::Text(json.into())).await
+}
+
+async fn telemetry(State(api): State<Api>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| stream_telemetry(socket, api.supplies))
+}
+
+async fn metrics(State(api): State<Api>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        crate::metrics::render(&api.supplies.states()),
+    )
+}
+
+fn router(supplies: Supplies, token: Option<String>, metrics_enabled: bool) -> Router {
+    let mut router = Router::new();
+    if metrics_enabled {
+        router = router.route("/metrics", get(metrics));
+    }
+    router
+        .route("/api/supplies", get(list))
+        .route("/api/telemetry", get(telemetry))
+        .route("/api/supplies/{supply}", get(show))
+        .route("/api/supplies/{supply}/actions", post(action))
+        .with_state(Api {
+            supplies,
+            token: token.map(Arc::from),
+        })
+}
+
+/// Serves the API if enabled in the configuration.
+pub async fn serve(config: ApiConfig, supplies: Supplies) {
+    if !config.enabled {
+        return;
+    }
+
+    let listener = match TcpListener::bind(&config.bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to start API on {}: {err}", config.bind);
+            return;
+        }
+    };
+    info!("API listening on http://{}", config.bind);
+    if let Err(err) = axum::serve(listener, router(supplies, config.token, config.metrics)).await {
+        error!("API server failed: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::supply;
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use futures::StreamExt;
+    use futures::channel::mpsc::unbounded;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+    use tower::ServiceExt;
+
+    fn post(uri: &str, token: Option<&str>, body: &str) -> Request<Body> {
+        let mut request = Request::post(uri).header("content-type", "application/json");
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        request.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_api() {
+        let supplies = Supplies::default();
+        let (tx, mut rx) = unbounded();
+        supplies.register(supply(&["psu", "fan"]), tx);
+        let app = router(supplies, Some("secret".to_string()), false);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/api/supplies/SIM")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let state: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(state["name"], "bench");
+        assert_eq!(state["channels"][1]["name"], "fan");
+
+        let set_voltage = r#"{"action": "set_voltage", "channel": "fan", "voltage": 12}"#;
+        let response = app
+            .clone()
+            .oneshot(post("/api/supplies/bench/actions", None, set_voltage))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .clone()
+            .oneshot(post(
+                "/api/supplies/bench/actions",
+                Some("secret"),
+                set_voltage,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert!(matches!(
+            rx.next().await,
+            Some(PowerSupplyAction::SetVoltage(1, 12.0))
+        ));
+
+        let response = app
+            .oneshot(post(
+                "/api/supplies/bench/actions",
+                Some("secret"),
+                r#"{"action": "off", "channel": "3"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_api_without_token() {
+        let supplies = Supplies::default();
+        let (tx, _rx) = unbounded();
+        supplies.register(supply(&["psu", "fan"]), tx);
+
+        let app = router(supplies, None, true);
+        let response = app
+            .clone()
+            .oneshot(post(
+                "/api/supplies/bench/actions",
+                Some(""),
+                r#"{"action": "trip_reset"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("power_supply_up{supply=\"SIM\",name=\"bench\"} 1\n"));
+    }
+
+    async fn next_frame(
+        ws: &mut WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>,
+    ) -> serde_json::Value {
+        let message = ws.next().await.unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_telemetry() {
+        let supplies = Supplies::default();
+        let (tx, _rx) = unbounded();
+        supplies.register(supply(&["psu", "fan"]), tx);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(supplies.clone(), None, false);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut ws, _) = connect_async(format!("ws://{addr}/api/telemetry"))
+            .await
+            .unwrap();
+        let frame = next_frame(&mut ws).await;
+        assert_eq!(frame["type"], "readings");
+        assert_eq!(frame["supply"], "SIM");
+        assert_eq!(frame["channels"][1]["name"], "fan");
+
+        let trip = Event::Trip {
+            channel: 2,
+            channel_name: "fan".to_string(),
+            trip: "OVER_CURRENT_TRIP",
+        };
+        supplies.publish("SIM", "bench", trip);
+        let frame = next_frame(&mut ws).await;
+        assert_eq!(frame["type"], "trip");
+        assert_eq!(frame["name"], "bench");
+        assert_eq!(frame["channel"], 2);
+        assert_eq!(frame["trip"], "OVER_CURRENT_TRIP");
+        assert!(frame["timestamp"].as_f64().unwrap() > 0.0);
+    }
+}
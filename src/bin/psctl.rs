@@ -0,0 +1,332 @@
+//! Command-line control of the power supplies configured in the GUI.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result, anyhow};
+use clap::{Args, Parser, Subcommand};
+use serde_json::{Value, json};
+
+use power_supply_control::{
+    config::{AppConfig, PowerSupplyConfig},
+    get_config_dir,
+    mx100qp::{Channel, Mx100qp},
+};
+
+#[derive(Parser)]
+#[command(
+    name = "psctl",
+    version,
+    about = "Controls power supplies stored in config.json"
+)]
+struct Cli {
+    /// Configuration file, defaults to the one used by the GUI.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Prints JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists configured power supplies.
+    List,
+    /// Shows readings of all channels.
+    Status {
+        supply: String,
+        /// Keeps polling until interrupted.
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Switches the channel on, or all channels if none is given.
+    On {
+        supply: String,
+        channel: Option<String>,
+    },
+    /// Switches the channel off, or all channels if none is given.
+    Off {
+        supply: String,
+        channel: Option<String>,
+    },
+    /// Changes settings of the channel and stores them in the configuration.
+    Set {
+        supply: String,
+        channel: String,
+        #[command(flatten)]
+        settings: Settings,
+    },
+    /// Switches all outputs off and applies the stored configuration.
+    Apply { supply: String },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+struct Settings {
+    #[arg(long)]
+    voltage: Option<f32>,
+    #[arg(long)]
+    current: Option<f32>,
+    /// Over-voltage trip in volts or `off`.
+    #[arg(long, value_parser = parse_trip)]
+    ovp: Option<Trip>,
+    /// Over-current trip in amps or `off`.
+    #[arg(long, value_parser = parse_trip)]
+    ocp: Option<Trip>,
+    /// Range as sent to the device, e.g. 1 for 35V/3A on MX100QP.
+    #[arg(long)]
+    vrange: Option<u8>,
+}
+
+/// Trip limit, `None` disables the trip.
+#[derive(Clone, Copy)]
+struct Trip(Option<f32>);
+
+fn parse_trip(value: &str) -> Result<Trip, String> {
+    if value.eq_ignore_ascii_case("off") {
+        return Ok(Trip(None));
+    }
+    value
+        .parse()
+        .map(|limit| Trip(Some(limit)))
+        .map_err(|_| format!("expected a number or off, got {value}"))
+}
+
+fn format_trip(trip: Option<f32>) -> String {
+    trip.map_or("off".to_string(), |limit| limit.to_string())
+}
+
+fn list(appconfig: &AppConfig, json: bool) {
+    let power_supplies = &appconfig.data.power_supplies;
+    if json {
+        let list: Vec<Value> = power_supplies
+            .iter()
+            .map(|config| {
+                json!({
+                    "name": config.name,
+                    "id": config.id,
+                    "model": config.model,
+                    "connection": config.connection.to_string(),
+                    "channels": config.channels.iter().map(|ch| &ch.name).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&list).unwrap());
+        return;
+    }
+
+    for config in power_supplies {
+        let channels: Vec<&str> = config.channels.iter().map(|ch| ch.name.as_str()).collect();
+        println!(
+            "{} ({} {}, {}): {}",
+            config.name,
+            config.model.as_deref().unwrap_or("unknown model"),
+            config.id,
+            config.connection,
+            channels.join(", ")
+        );
+    }
+}
+
+fn channel_json(config: &PowerSupplyConfig, channel: &Channel) -> Value {
+    json!({
+        "channel": channel.index + 1,
+        "name": config.channels.get(channel.index as usize).map(|ch| &ch.name),
+        "enabled": channel.enabled,
+        "voltage": channel.voltage.current,
+        "voltage_set": channel.voltage.set,
+        "current": channel.current.current,
+        "current_set": channel.current.set,
+        "vrange": channel.vrange,
+        "ovp": channel.overvoltage_trip,
+        "ocp": channel.overcurrent_trip,
+        "status": channel.status.iter_names().map(|(name, _)| name).collect::<Vec<_>>(),
+    })
+}
+
+fn print_status(config: &PowerSupplyConfig, channels: &[Channel], json: bool, pretty: bool) {
+    if json {
+        let status = json!({
+            "name": config.name,
+            "channels": channels.iter().map(|ch| channel_json(config, ch)).collect::<Vec<_>>(),
+        });
+        match pretty {
+            true => println!("{}", serde_json::to_string_pretty(&status).unwrap()),
+            false => println!("{status}"),
+        }
+        return;
+    }
+
+    for channel in channels {
+        let name = config
+            .channels
+            .get(channel.index as usize)
+            .map_or("", |ch| ch.name.as_str());
+        let status: Vec<&str> = channel.status.iter_names().map(|(name, _)| name).collect();
+        println!(
+            "{} {:<16} {:<3} {:>7.3} V ({:.3} V) {:>6.3} A ({:.3} A) OVP {} OCP {} {}",
+            channel.index + 1,
+            name,
+            if channel.enabled { "ON" } else { "OFF" },
+            channel.voltage.current,
+            channel.voltage.set,
+            channel.current.current,
+            channel.current.set,
+            format_trip(channel.overvoltage_trip),
+            format_trip(channel.overcurrent_trip),
+            status.join(" "),
+        );
+    }
+}
+
+/// Opens the supply and adapts its configuration to the detected model.
+async fn connect(appconfig: &mut AppConfig, supply: &str) -> Result<(Mx100qp, PowerSupplyConfig)> {
+    let id = appconfig
+        .find_power_supply(supply)
+        .ok_or_else(|| anyhow!("Unknown power supply {supply}"))?
+        .id
+        .clone();
+
+    let config = appconfig
+        .power_supply_mut(&id)
+        .ok_or_else(|| anyhow!("Unknown power supply {supply}"))?;
+    let port = Mx100qp::open(config)
+        .await
+        .with_context(|| format!("Failed to open {}", config.name))?;
+
+    let adapted = config.adapt_to_model(port.model);
+    let config = config.clone();
+    if adapted {
+        appconfig.save();
+    }
+
+    Ok((port, config))
+}
+
+fn find_channel(config: &PowerSupplyConfig, channel: &str) -> Result<u8> {
+    config
+        .find_channel(channel)
+        .ok_or_else(|| anyhow!("Unknown channel {channel} of {}", config.name))
+}
+
+async fn status(appconfig: &mut AppConfig, supply: &str, watch: bool, json: bool) -> Result<()> {
+    let (mut port, config) = connect(appconfig, supply).await?;
+
+    if !watch {
+        let channels = port.read_channels().await?;
+        print_status(&config, &channels, json, true);
+        return Ok(());
+    }
+
+    let schedule = config.poll.clone();
+    let mut polls: u32 = 0;
+    loop {
+        let read_settings = polls.is_multiple_of(schedule.settings_every.max(1));
+        polls = polls.wrapping_add(1);
+        let channels = port.poll(read_settings).await?;
+        print_status(&config, &channels, json, false);
+        if !json {
+            println!();
+        }
+        tokio::time::sleep(Duration::from_millis(schedule.interval_ms)).await;
+    }
+}
+
+async fn switch(
+    appconfig: &mut AppConfig,
+    supply: &str,
+    channel: Option<String>,
+    on: bool,
+) -> Result<()> {
+    let (mut port, config) = connect(appconfig, supply).await?;
+    match (channel, on) {
+        (Some(channel), true) => port.channel_on(find_channel(&config, &channel)?).await?,
+        (Some(channel), false) => port.channel_off(find_channel(&config, &channel)?).await?,
+        (None, true) => port.all_channel_on().await?,
+        (None, false) => port.all_channel_off().await?,
+    }
+    Ok(())
+}
+
+async fn set(
+    appconfig: &mut AppConfig,
+    supply: &str,
+    channel: &str,
+    settings: Settings,
+) -> Result<()> {
+    let (mut port, config) = connect(appconfig, supply).await?;
+    let ch = find_channel(&config, channel)?;
+    let Settings {
+        voltage,
+        current,
+        ovp,
+        ocp,
+        vrange,
+    } = settings;
+
+    let mut conf = config.channels[ch as usize].clone();
+    conf.voltage = voltage.unwrap_or(conf.voltage);
+    conf.current = current.unwrap_or(conf.current);
+
+    let mut vrange = vrange;
+    if vrange.is_none() && conf.auto_vrange && (voltage.is_some() || current.is_some()) {
+        vrange = port.model.auto_vrange(ch, conf.voltage, conf.current);
+    }
+
+    if let Some(vrange) = vrange {
+        conf.vrange = vrange;
+        port.set_vrange(ch, vrange).await?;
+    }
+    if voltage.is_some() {
+        port.set_voltage(ch, conf.voltage).await?;
+    }
+    if current.is_some() {
+        port.set_current(ch, conf.current).await?;
+    }
+    if let Some(Trip(limit)) = ovp {
+        conf.overvoltage_trip = limit;
+        port.set_overvoltage_trip(ch, limit).await?;
+    }
+    if let Some(Trip(limit)) = ocp {
+        conf.overcurrent_trip = limit;
+        port.set_overcurrent_trip(ch, limit).await?;
+    }
+
+    *appconfig
+        .power_supply_channel(&config.id, ch)
+        .ok_or_else(|| anyhow!("Unknown channel {}", ch + 1))? = conf;
+    appconfig.save();
+    Ok(())
+}
+
+async fn apply(appconfig: &mut AppConfig, supply: &str) -> Result<()> {
+    let (mut port, config) = connect(appconfig, supply).await?;
+    port.apply_config(&config).await?;
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let path = cli
+        .config
+        .unwrap_or_else(|| get_config_dir().join("config.json"));
+    let mut appconfig = AppConfig::load_from_file(path)?;
+
+    match cli.command {
+        Command::List => {
+            list(&appconfig, cli.json);
+            Ok(())
+        }
+        Command::Status { supply, watch } => status(&mut appconfig, &supply, watch, cli.json).await,
+        Command::On { supply, channel } => switch(&mut appconfig, &supply, channel, true).await,
+        Command::Off { supply, channel } => switch(&mut appconfig, &supply, channel, false).await,
+        Command::Set {
+            supply,
+            channel,
+            settings,
+        } => set(&mut appconfig, &supply, &channel, settings).await,
+        Command::Apply { supply } => apply(&mut appconfig, &supply).await,
+    }
+}
@@ -5,49 +5,51 @@ use dioxus::{
     hooks::{use_context, use_signal},
     signals::Signal,
 };
-use serialport::UsbPortInfo;
 
-use crate::config::{AppConfig, ChannelConfig, MultiOn, PowerSupplyConfig};
+use power_supply_control::{
+    config::{AppConfig, Connection, PowerSupplyConfig},
+    discovery::{self, DiscoveredSupply},
+    models::{self, Model},
+    mx100qp::Mx100qp,
+    transport::LAN_PORT,
+};
 
-fn format_usb_port(port: &UsbPortInfo) -> String {
-    [
-        Some(format!("{:04x}:{:04x}", port.vid, port.pid)),
-        port.manufacturer.clone(),
-        port.product.clone(),
-        port.serial_number.clone(),
-    ]
-    .into_iter()
-    .flatten()
-    .collect::<Vec<String>>()
-    .join(", ")
+/// Creates configuration of a new supply, adapted to the model once connected.
+fn new_power_supply(connection: Connection, id: String, model: &Model) -> PowerSupplyConfig {
+    let mut config = PowerSupplyConfig::new(connection, id, format!("Power Supply {}", model.name));
+    config.adapt_to_model(model);
+    config
 }
 
 pub fn AddDeviceComponent() -> Element {
     let mut appconfig = use_context::<Signal<AppConfig>>();
 
-    let scan_usb = move || -> Vec<UsbPortInfo> {
-        serialport::available_ports()
-            .unwrap()
-            .into_iter()
-            .filter_map(|p| match p.port_type {
-                serialport::SerialPortType::UsbPort(usbinfo) => Some(usbinfo),
-                _ => None,
-            })
-            .filter(|p| {
-                for supply in appconfig.read().data.power_supplies.iter() {
-                    if supply.vid == p.vid
-                        && supply.pid == p.pid
-                        && supply.serial_number == p.serial_number
-                    {
-                        return false;
-                    }
-                }
-                true
-            })
-            .collect()
+    let mut supplies = use_signal(Vec::<DiscoveredSupply>::new);
+    let mut scanning = use_signal(|| false);
+    let mut all_ports = use_signal(|| false);
+
+    let scan = move || {
+        spawn(async move {
+            scanning.set(true);
+            let found = discovery::discover(all_ports())
+                .await
+                .into_iter()
+                .filter(|supply| {
+                    !appconfig
+                        .read()
+                        .data
+                        .power_supplies
+                        .iter()
+                        .any(|configured| configured.id == supply.identity.serial_number)
+                })
+                .collect();
+            supplies.set(found);
+            scanning.set(false);
+        });
     };
+    use_hook(scan);
 
-    let mut ports = use_signal(scan_usb);
+    let mut lan_error = use_signal(|| None::<String>);
 
     rsx! {
         form {
@@ -57,55 +59,128 @@ pub fn AddDeviceComponent() -> Element {
                     Some(idx) => idx.as_value().parse().unwrap(),
                     None => return,
                 };
-                let mut usb_ports = ports.write();
-                let port = &usb_ports[index];
+                let supply = supplies.write().remove(index);
                 appconfig
                     .write()
                     .data
                     .power_supplies
-                    .push(PowerSupplyConfig {
-                        vid: port.vid,
-                        pid: port.pid,
-                        serial_number: port.serial_number.clone(),
-                        id: port.serial_number.clone().unwrap(),
-                        name: "Power Supply MX100QP".to_string(),
-                        voltage_tracking: 0,
-                        channels: (1..=4)
-                            .map(|ch| {
-                                ChannelConfig {
-                                    name: format!("Channel {ch}"),
-                                    voltage: 0.0,
-                                    current: 0.0,
-                                    vrange: 1,
-                                    auto_vrange: true,
-                                    overcurrent_trip: None,
-                                    overvoltage_trip: None,
-                                    multi_on: MultiOn {
-                                        enabled: true,
-                                        delay_ms: 0,
-                                    },
-                                }
-                            })
-                            .collect(),
-                    });
+                    .push(
+                        new_power_supply(
+                            supply.connection,
+                            supply.identity.serial_number,
+                            supply.model,
+                        ),
+                    );
                 appconfig.write().save();
-                *usb_ports = scan_usb();
             },
 
             button {
                 class: "btn btn-sm btn-secondary",
+                disabled: scanning(),
                 onclick: move |evt| {
                     evt.prevent_default();
-                    *ports.write() = scan_usb();
+                    scan();
                 },
-                "Rescan USB"
+                if scanning() {
+                    "Scanning..."
+                } else {
+                    "Rescan"
+                }
+            }
+            label {
+                class: "input-group-text",
+                title: "Probe also ports other than USB-serial bridges, writing *IDN? to any device on them",
+                input {
+                    class: "form-check-input mt-0 me-1",
+                    r#type: "checkbox",
+                    checked: all_ports(),
+                    onchange: move |evt| all_ports.set(evt.checked()),
+                }
+                "All ports"
             }
             select { class: "form-control form-control-sm", name: "index",
-                for (i , port) in ports.read().iter().enumerate() {
-                    option { value: format!("{i}"), "{format_usb_port(port)}" }
+                for (i , supply) in supplies.read().iter().enumerate() {
+                    option { value: format!("{i}"), "{supply}" }
                 }
             }
             button { class: "btn btn-sm btn-success", "Add" }
         }
+
+        form {
+            class: "input-group mt-1",
+            onsubmit: move |evt| {
+                let values = evt.data.values();
+                let host = values["host"].as_value();
+                let port = values["port"].as_value().parse().unwrap_or(LAN_PORT);
+                spawn(async move {
+                    let connection = Connection::Tcp { host, port };
+                    match Mx100qp::identify(&connection).await {
+                        Ok(identity) => {
+                            let Some(model) = models::find(&identity.model) else {
+                                lan_error
+                                    .set(Some(format!("{connection}: Unsupported model {}", identity.model)));
+                                return;
+                            };
+                            appconfig
+                                .write()
+                                .data
+                                .power_supplies
+                                .push(new_power_supply(connection, identity.serial_number, model));
+                            appconfig.write().save();
+                            lan_error.set(None);
+                        }
+                        Err(err) => lan_error.set(Some(format!("{connection}: {err}"))),
+                    }
+                });
+            },
+
+            span { class: "input-group-text", "LAN" }
+            input {
+                class: "form-control form-control-sm",
+                name: "host",
+                placeholder: "host",
+                required: true,
+                autocomplete: "off",
+            }
+            input {
+                class: "form-control form-control-sm",
+                r#type: "number",
+                name: "port",
+                value: "{LAN_PORT}",
+                min: 1,
+                max: 65535,
+                autocomplete: "off",
+            }
+            button { class: "btn btn-sm btn-success", "Add" }
+        }
+        if let Some(err) = lan_error() {
+            div { class: "alert alert-danger mt-1 p-1 mb-0", {err} }
+        }
+
+        div { class: "mt-1",
+            button {
+                class: "btn btn-sm btn-outline-secondary",
+                onclick: move |_| {
+                    let id = (1..)
+                        .map(|n| format!("SIM-{n}"))
+                        .find(|id| {
+                            !appconfig.read().data.power_supplies.iter().any(|supply| &supply.id == id)
+                        })
+                        .unwrap();
+                    let mut power_supply = new_power_supply(
+                        Connection::Simulator {
+                            simulator: id.clone(),
+                            simulated_model: None,
+                        },
+                        id,
+                        &models::MX100QP,
+                    );
+                    power_supply.name = "Virtual MX100QP".to_string();
+                    appconfig.write().data.power_supplies.push(power_supply);
+                    appconfig.write().save();
+                },
+                "Add virtual supply"
+            }
+        }
     }
 }
@@ -1,21 +1,17 @@
-use crate::{
-    components::{
-        add_device::AddDeviceComponent,
-        edit_mode::{EditMode, EditModeComponent},
-        power_supply::PowerSupplyComponent,
-    },
-    config::AppConfig,
-    get_config_dir,
+use crate::components::{
+    add_device::AddDeviceComponent,
+    edit_mode::{EditMode, EditModeComponent},
+    power_supply::PowerSupplyComponent,
+    sequencer::SequencerComponent,
 };
 use dioxus::prelude::*;
+use power_supply_control::config::AppConfig;
 
 #[component]
 pub fn AppComponent() -> Element {
-    let config = use_context_provider(move || {
-        Signal::new(AppConfig::load_from_file(
-            get_config_dir().join("config.json"),
-        ))
-    });
+    // loaded by main before the launch
+    let loaded = use_hook(consume_context::<AppConfig>);
+    let config = use_context_provider(move || Signal::new(loaded));
 
     let edit_mode = use_context_provider(|| Signal::new(EditMode(false)));
 
@@ -23,7 +19,10 @@ pub fn AppComponent() -> Element {
         style { {include_str!("../../assets/bootstrap.css")} }
         style { {include_str!("../../assets/main.css")} }
 
-        div { class: "d-flex flex-row-reverse", EditModeComponent {} }
+        div { class: "d-flex flex-row-reverse",
+            EditModeComponent {}
+            SequencerComponent {}
+        }
 
         for config in *config.read().data.power_supplies {
             PowerSupplyComponent { id: config.id.clone() }
@@ -1,19 +1,43 @@
-use crate::mx100qp::LimitEventStatus;
-use crate::{
-    components::{
-        edit_mode::EditMode,
-        editable_text::EditableTextComponent,
-        input_unit::InputUnitComponent,
-        power_supply::{ChannelSelection, PowerSupplyAction},
-    },
-    config::ChannelConfig,
-    mx100qp::{Channel, VRANGES, VoltageTrackingState},
+use crate::components::{
+    edit_mode::EditMode,
+    editable_text::EditableTextComponent,
+    history_chart::HistoryChartComponent,
+    input_unit::InputUnitComponent,
+    power_supply::{ChannelSelection, PowerSupplyAction},
 };
+use crate::energy::Energy;
+use crate::history::{ChannelHistory, Quantity, WINDOWS};
+use crate::stats::ChannelStats;
 use dioxus::prelude::*;
+use power_supply_control::mx100qp::LimitEventStatus;
+use power_supply_control::{
+    config::ChannelConfig,
+    models::Model,
+    mx100qp::{Channel, VoltageTrackingState},
+};
+use std::time::Instant;
 
 #[component]
-pub fn ChannelComponent(channel: Channel, config: ChannelConfig) -> Element {
+pub fn ChannelComponent(
+    channel: Channel,
+    config: ChannelConfig,
+    model: &'static Model,
+    history: Signal<Vec<ChannelHistory>>,
+    energy: Signal<Vec<Energy>>,
+    stats: Signal<Vec<ChannelStats>>,
+) -> Element {
     let edit_mode = use_context::<Signal<EditMode>>();
+    let mut window = use_signal(|| 0usize);
+    let mut expanded = use_signal(|| false);
+    let window_select = rsx! {
+        select {
+            class: "form-control form-control-sm w-auto",
+            onchange: move |evt| window.set(evt.value().parse().unwrap_or(0)),
+            for (i , (_ , label)) in WINDOWS.iter().enumerate() {
+                option { value: "{i}", selected: window() == i, {*label} }
+            }
+        }
+    };
     let power_supply_action = use_coroutine_handle::<PowerSupplyAction>();
     let card_class = if channel.enabled { "success" } else { "danger" };
     let mut errors = Vec::new();
@@ -50,7 +74,8 @@ pub fn ChannelComponent(channel: Channel, config: ChannelConfig) -> Element {
         });
     }
 
-    if channel.vrange != config.vrange {
+    let has_ranges = model.has_ranges(channel.index);
+    if has_ranges && channel.vrange != config.vrange {
         errors.push("Different VRange is set!".to_string());
     }
 
@@ -75,7 +100,7 @@ pub fn ChannelComponent(channel: Channel, config: ChannelConfig) -> Element {
                                 .send(PowerSupplyAction::RenameChannel(channel.index, new_name));
                         },
                         disabled: !edit_mode.read().0,
-                        text: config.name,
+                        text: config.name.clone(),
                     }
                 }
 
@@ -92,7 +117,12 @@ pub fn ChannelComponent(channel: Channel, config: ChannelConfig) -> Element {
                         class: "btn btn-sm btn-danger",
                         onclick: move |_| {
                             power_supply_action
-                                .send(PowerSupplyAction::Off(ChannelSelection::Channel(channel.index)))
+                                .send(
+                                    PowerSupplyAction::Off(
+                                        ChannelSelection::Channel(channel.index),
+                                        Instant::now(),
+                                    ),
+                                )
                         },
                         "OFF"
                     }
@@ -107,6 +137,32 @@ pub fn ChannelComponent(channel: Channel, config: ChannelConfig) -> Element {
                     div { class: "text-end text-muted", "{channel.current.set:.3} A" }
                 }
 
+                HistoryChartComponent {
+                    history,
+                    channel: channel.index,
+                    quantity: Quantity::Voltage,
+                    window: WINDOWS[window()].0,
+                    height: 40,
+                }
+                HistoryChartComponent {
+                    history,
+                    channel: channel.index,
+                    quantity: Quantity::Current,
+                    window: WINDOWS[window()].0,
+                    height: 40,
+                }
+                EnergyComponent { energy, channel: channel.index }
+                StatsComponent { stats, channel: channel.index }
+
+                div { class: "d-flex gap-1 mt-1 mb-1",
+                    {window_select.clone()}
+                    button {
+                        class: "btn btn-sm btn-outline-secondary",
+                        onclick: move |_| expanded.set(true),
+                        "Expand"
+                    }
+                }
+
                 if edit_mode.read().0 {
                     if channel.voltage_tracking != VoltageTrackingState::Slave {
                         InputUnitComponent {
@@ -154,45 +210,47 @@ pub fn ChannelComponent(channel: Channel, config: ChannelConfig) -> Element {
                         },
                     }
 
-                    div { class: "input-group input-group-sm",
-                        span { class: "input-group-text form-switch",
-                            "Auto VRANGE"
+                    if has_ranges {
+                        div { class: "input-group input-group-sm",
+                            span { class: "input-group-text form-switch",
+                                "Auto VRANGE"
+
+                                input {
+                                    r#type: "checkbox",
+                                    class: "form-check-input ms-1",
+                                    autocomplete: "off",
+                                    checked: config.auto_vrange,
+                                    disabled: !edit_mode.read().0,
+                                    onchange: move |evt| {
+                                        power_supply_action
+                                            .send(
+                                                PowerSupplyAction::SetAutoVRange(
+                                                    channel.index,
+                                                    evt.data.value().parse::<bool>().unwrap(),
+                                                ),
+                                            );
+                                    },
+                                }
+                            }
 
-                            input {
-                                r#type: "checkbox",
-                                class: "form-check-input ms-1",
-                                autocomplete: "off",
-                                checked: config.auto_vrange,
-                                disabled: !edit_mode.read().0,
+                            select {
+                                class: "form-control form-control-sm",
+                                disabled: config.auto_vrange || !edit_mode.read().0,
                                 onchange: move |evt| {
                                     power_supply_action
                                         .send(
-                                            PowerSupplyAction::SetAutoVRange(
+                                            PowerSupplyAction::SetVRange(
                                                 channel.index,
-                                                evt.data.value().parse::<bool>().unwrap(),
+                                                evt.data.value().parse().unwrap(),
                                             ),
                                         );
                                 },
-                            }
-                        }
-
-                        select {
-                            class: "form-control form-control-sm",
-                            disabled: config.auto_vrange || !edit_mode.read().0,
-                            onchange: move |evt| {
-                                power_supply_action
-                                    .send(
-                                        PowerSupplyAction::SetVRange(
-                                            channel.index,
-                                            evt.data.value().parse().unwrap(),
-                                        ),
-                                    );
-                            },
-                            for (i , range) in VRANGES[channel.index as usize].iter().enumerate() {
-                                option {
-                                    selected: config.vrange as usize == i,
-                                    value: "{i}",
-                                    "{range}"
+                                for (i , range) in model.selectable_ranges(channel.index) {
+                                    option {
+                                        selected: config.vrange == i,
+                                        value: "{i}",
+                                        "{range}"
+                                    }
                                 }
                             }
                         }
@@ -210,5 +268,149 @@ pub fn ChannelComponent(channel: Channel, config: ChannelConfig) -> Element {
                 }
             }
         }
+
+        if expanded() {
+            div { class: "modal fade show d-block",
+                div { class: "modal-dialog modal-xl",
+                    div { class: "modal-content",
+                        div { class: "modal-header gap-2",
+                            h1 { class: "modal-title fs-5 flex-grow-1", {config.name.clone()} }
+                            {window_select}
+                            button {
+                                class: "btn-close",
+                                onclick: move |_| expanded.set(false),
+                            }
+                        }
+                        div { class: "modal-body",
+                            for quantity in [Quantity::Voltage, Quantity::Current] {
+                                HistoryChartComponent {
+                                    history,
+                                    channel: channel.index,
+                                    quantity,
+                                    window: WINDOWS[window()].0,
+                                    height: 200,
+                                }
+                            }
+                            div { class: "d-flex gap-3 small text-muted mt-1",
+                                span { class: "text-primary", "readback" }
+                                span { "- - setpoint" }
+                                span { class: "text-danger", "- - trip threshold" }
+                                span { class: "text-success", "| on" }
+                                span { "| off" }
+                                span { class: "text-danger", "| trip" }
+                            }
+                        }
+                    }
+                }
+            }
+            div { class: "modal-backdrop fade show" }
+        }
+    }
+}
+
+/// Power of the last poll with energy and charge since the last reset.
+#[component]
+fn EnergyComponent(energy: Signal<Vec<Energy>>, channel: u8) -> Element {
+    let Some(totals) = energy.read().get(channel as usize).cloned() else {
+        return rsx! {};
+    };
+    let index = channel as usize;
+    let elapsed = totals.elapsed.as_secs();
+    let elapsed = format!(
+        "{}:{:02}:{:02}",
+        elapsed / 3600,
+        elapsed / 60 % 60,
+        elapsed % 60
+    );
+
+    rsx! {
+        div { class: "d-flex gap-2 small mt-1",
+            span { title: "Power", "{totals.power:.3} W" }
+            span { title: "Energy", "{totals.watt_hours:.4} Wh" }
+            span { title: "Charge", "{totals.amp_hours * 1000.0:.2} mAh" }
+            span { class: "text-muted flex-grow-1 text-end", title: "Integrated over", "{elapsed}" }
+        }
+        div { class: "btn-group btn-group-sm mt-1",
+            if totals.running {
+                button {
+                    class: "btn btn-outline-secondary",
+                    onclick: move |_| energy.write()[index].stop(),
+                    "Stop"
+                }
+            } else {
+                button {
+                    class: "btn btn-outline-secondary",
+                    onclick: move |_| energy.write()[index].start(),
+                    "Start"
+                }
+            }
+            button {
+                class: "btn btn-outline-secondary",
+                onclick: move |_| energy.write()[index].reset(),
+                "Reset"
+            }
+        }
+    }
+}
+
+/// A new current peak is highlighted for this long.
+const PEAK_HIGHLIGHT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Minimum, maximum, mean and RMS of the readbacks with the held current peak.
+#[component]
+fn StatsComponent(stats: Signal<Vec<ChannelStats>>, channel: u8) -> Element {
+    let index = channel as usize;
+    let Some(channel_stats) = stats.read().get(index).cloned() else {
+        return rsx! {};
+    };
+    let Some(summary) = channel_stats.summary() else {
+        return rsx! {};
+    };
+    let since_peak = channel_stats
+        .peak_at
+        .map(|at| at.elapsed())
+        .unwrap_or_default();
+    let peak_class = match since_peak < PEAK_HIGHLIGHT {
+        true => "badge text-bg-warning",
+        false => "badge text-bg-secondary",
+    };
+
+    rsx! {
+        table { class: "table table-sm small mb-0 mt-1",
+            thead {
+                tr {
+                    th {}
+                    th { "min" }
+                    th { "max" }
+                    th { "mean" }
+                    th { "RMS" }
+                }
+            }
+            tbody {
+                for (label , unit , value) in [("V", "V", summary.voltage), ("I", "A", summary.current)] {
+                    tr {
+                        th { "{label}" }
+                        td { "{value.min:.3}" }
+                        td { "{value.max:.3}" }
+                        td { "{value.mean:.3}" }
+                        td { title: "{unit}", "{value.rms:.3}" }
+                    }
+                }
+            }
+        }
+        div { class: "d-flex gap-2 small align-items-center mt-1",
+            span {
+                class: peak_class,
+                title: "Peak held since the reset, raised {since_peak.as_secs()} s ago",
+                "HOLD"
+            }
+            span { class: "flex-grow-1", "Ipk {summary.current.max:.3} A" }
+            span { class: "text-muted", title: "Polls sampled", "n={summary.samples}" }
+            button {
+                class: "btn btn-sm btn-outline-secondary",
+                onclick: move |_| stats.write()[index].reset(),
+                "Reset stats"
+            }
+        }
     }
 }
@@ -1,11 +1,26 @@
 use dioxus::prelude::*;
 
-use crate::{components::power_supply::PowerSupplyAction, mx100qp::MultiChannelOn};
+use crate::components::power_supply::PowerSupplyAction;
+use power_supply_control::mx100qp::{MultiChannelOff, MultiChannelOn};
 
+/// Delay of the channel in the multi-on, or the multi-off when `off` is set.
 #[component]
-pub fn ChannelDelayComponent(channel: u8, enabled: bool, delay_ms: u32) -> Element {
+pub fn ChannelDelayComponent(channel: u8, off: bool, enabled: bool, delay_ms: u32) -> Element {
     let power_supply_action = use_coroutine_handle::<PowerSupplyAction>();
     let mut delay_ms = use_signal(|| delay_ms);
+    let send = move |delay_ms: Option<u32>| {
+        let action = match off {
+            false => PowerSupplyAction::SetMultiChannel(
+                channel,
+                delay_ms.map_or(MultiChannelOn::Disabled, MultiChannelOn::Delay),
+            ),
+            true => PowerSupplyAction::SetMultiChannelOff(
+                channel,
+                delay_ms.map_or(MultiChannelOff::Disabled, MultiChannelOff::Delay),
+            ),
+        };
+        power_supply_action.send(action);
+    };
 
     rsx! {
         span { class: "input-group-text", class: if !enabled { "disabled" },
@@ -16,17 +31,8 @@ pub fn ChannelDelayComponent(channel: u8, enabled: bool, delay_ms: u32) -> Eleme
                 checked: enabled,
                 autocomplete: "off",
                 onchange: move |evt| {
-                    power_supply_action
-                        .send(
-                            PowerSupplyAction::SetMultiChannel(
-                                channel,
-                                if evt.data.value().parse().unwrap() {
-                                    MultiChannelOn::Delay(*delay_ms.read())
-                                } else {
-                                    MultiChannelOn::Disabled
-                                },
-                            ),
-                        );
+                    let enabled: bool = evt.data.value().parse().unwrap();
+                    send(enabled.then(|| *delay_ms.read()));
                 },
             }
         }
@@ -43,13 +49,7 @@ pub fn ChannelDelayComponent(channel: u8, enabled: bool, delay_ms: u32) -> Eleme
             },
             onchange: move |evt| {
                 delay_ms.set(evt.value().parse().unwrap_or(0));
-                power_supply_action
-                    .send(
-                        PowerSupplyAction::SetMultiChannel(
-                            channel,
-                            MultiChannelOn::Delay(*delay_ms.read()),
-                        ),
-                    );
+                send(Some(*delay_ms.read()));
             },
         }
         span { class: "input-group-text ps-1", class: if !enabled { "disabled" }, "ms" }
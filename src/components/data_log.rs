@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+use log::{error, warn};
+use power_supply_control::{
+    config::{AppConfig, LogFormat},
+    get_config_dir,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{datalog::DataLogger, supplies::Supplies};
+
+/// Starts and stops logging of the readings of a supply into files in the configuration directory.
+#[component]
+pub fn DataLogComponent(id: String, name: String) -> Element {
+    let supplies = use_context::<Supplies>();
+    let appconfig = use_context::<Signal<AppConfig>>();
+    let mut session = use_signal(String::new);
+    let mut format = use_signal(|| appconfig.read().data.datalog.format);
+    let mut running = use_signal(|| None::<Task>);
+    let mut path = use_signal(|| None::<PathBuf>);
+    let mut failure = use_signal(|| None::<String>);
+
+    let start = move |_| {
+        let config = appconfig.read().data.datalog.clone();
+        let dir = get_config_dir().join("logs");
+        let mut logger = match DataLogger::create(&dir, &name, &session(), format(), &config) {
+            Ok(logger) => logger,
+            Err(err) => {
+                error!("Failed to start data log of {name}: {err}");
+                failure.set(Some(err.to_string()));
+                return;
+            }
+        };
+        failure.set(None);
+        path.set(Some(logger.path().to_path_buf()));
+
+        // the logger is dropped with the task, finishing its file
+        let mut events = supplies.subscribe();
+        let id = id.clone();
+        let name = name.clone();
+        let task = spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(frame) if frame.supply == id => {
+                        if let Err(err) = logger.log(&frame) {
+                            error!("Data log {:?} failed: {err}", logger.path());
+                            failure.set(Some(err.to_string()));
+                            break;
+                        }
+                        if path.peek().as_deref() != Some(logger.path()) {
+                            path.set(Some(logger.path().to_path_buf()));
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(dropped)) => {
+                        warn!(
+                            "Data log {:?} lagged, {dropped} frames dropped",
+                            logger.path()
+                        );
+                        if let Err(err) = logger.gap(&id, &name) {
+                            error!("Data log {:?} failed: {err}", logger.path());
+                            failure.set(Some(err.to_string()));
+                            break;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+            running.set(None);
+        });
+        running.set(Some(task));
+    };
+
+    let file_name = path
+        .read()
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    rsx! {
+        div { class: "input-group input-group-sm w-auto",
+            if running.read().is_some() {
+                span {
+                    class: "input-group-text text-danger",
+                    title: path.read().as_ref().map(|path| path.display().to_string()),
+                    "● {file_name}"
+                }
+                button {
+                    class: "btn btn-sm btn-outline-danger",
+                    onclick: move |_| {
+                        if let Some(task) = running.take() {
+                            task.cancel();
+                        }
+                    },
+                    "Stop log"
+                }
+            } else {
+                input {
+                    class: "form-control form-control-sm",
+                    style: "width: 120px",
+                    placeholder: "session",
+                    value: "{session}",
+                    autocomplete: "off",
+                    oninput: move |evt| session.set(evt.value()),
+                }
+                select {
+                    class: "form-control form-control-sm w-auto",
+                    onchange: move |evt| {
+                        format
+                            .set(
+                                match evt.value().as_str() {
+                                    "parquet" => LogFormat::Parquet,
+                                    _ => LogFormat::Csv,
+                                },
+                            )
+                    },
+                    for option_format in [LogFormat::Csv, LogFormat::Parquet] {
+                        option {
+                            value: option_format.extension(),
+                            selected: format() == option_format,
+                            {option_format.extension().to_uppercase()}
+                        }
+                    }
+                }
+                button {
+                    class: "btn btn-sm btn-outline-secondary",
+                    onclick: start,
+                    "Log"
+                }
+            }
+        }
+        if let Some(failure) = failure() {
+            span { class: "small text-danger align-self-center", {failure} }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use dioxus::prelude::*;
+
+use crate::history::{ChannelHistory, MarkerKind, Quantity, chart};
+
+fn marker_color(kind: MarkerKind) -> &'static str {
+    match kind {
+        MarkerKind::On => "var(--bs-success)",
+        MarkerKind::Off => "var(--bs-secondary)",
+        MarkerKind::Trip => "var(--bs-danger)",
+    }
+}
+
+/// Scrolling chart of a channel readback with its setpoint, trip threshold and event markers.
+#[component]
+pub fn HistoryChartComponent(
+    history: Signal<Vec<ChannelHistory>>,
+    channel: u8,
+    quantity: Quantity,
+    window: Duration,
+    height: u32,
+) -> Element {
+    const WIDTH: f32 = 300.0;
+    let height_f = height as f32;
+    let history = history.read();
+    let Some(channel_history) = history.get(channel as usize) else {
+        return rsx! {};
+    };
+    let chart = chart(
+        channel_history,
+        quantity,
+        window,
+        Instant::now(),
+        WIDTH,
+        height_f,
+    );
+    let y = |value: f32| height_f - value / chart.max * height_f;
+    let unit = quantity.unit();
+
+    rsx! {
+        div { class: "position-relative",
+            span {
+                class: "position-absolute top-0 start-0 small text-muted",
+                style: "font-size: 0.7em",
+                "{chart.max:.2} {unit}"
+            }
+            svg {
+                class: "w-100 border-bottom",
+                height: "{height}",
+                view_box: "0 0 {WIDTH} {height}",
+                preserve_aspect_ratio: "none",
+                for marker in chart.markers {
+                    line {
+                        x1: "{marker.x}",
+                        x2: "{marker.x}",
+                        y1: "0",
+                        y2: "{height}",
+                        stroke: marker_color(marker.kind),
+                        stroke_width: "1",
+                        vector_effect: "non-scaling-stroke",
+                        title { {marker.label} }
+                    }
+                }
+                if let Some(setpoint) = chart.setpoint {
+                    line {
+                        x1: "0",
+                        x2: "{WIDTH}",
+                        y1: "{y(setpoint)}",
+                        y2: "{y(setpoint)}",
+                        stroke: "var(--bs-secondary)",
+                        stroke_width: "1",
+                        stroke_dasharray: "4 3",
+                        vector_effect: "non-scaling-stroke",
+                    }
+                }
+                if let Some(limit) = chart.limit {
+                    line {
+                        x1: "0",
+                        x2: "{WIDTH}",
+                        y1: "{y(limit)}",
+                        y2: "{y(limit)}",
+                        stroke: "var(--bs-danger)",
+                        stroke_width: "1",
+                        stroke_dasharray: "2 2",
+                        vector_effect: "non-scaling-stroke",
+                    }
+                }
+                polyline {
+                    points: chart.points,
+                    fill: "none",
+                    stroke: "var(--bs-primary)",
+                    stroke_width: "1.5",
+                    vector_effect: "non-scaling-stroke",
+                }
+            }
+        }
+    }
+}
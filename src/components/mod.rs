@@ -2,8 +2,13 @@ pub mod add_device;
 pub mod app;
 pub mod channel;
 pub mod channel_delay;
+#[cfg(feature = "datalog")]
+pub mod data_log;
 pub mod edit_mode;
 pub mod editable_text;
+pub mod history_chart;
 pub mod input_unit;
 pub mod modal;
 pub mod power_supply;
+pub mod sequencer;
+pub mod setup_stores;
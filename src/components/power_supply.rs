@@ -1,23 +1,43 @@
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::time::{Duration, Instant};
 
 use crate::components::channel_delay::ChannelDelayComponent;
+#[cfg(feature = "datalog")]
+use crate::components::data_log::DataLogComponent;
 use crate::components::edit_mode::EditMode;
 use crate::components::modal::ModalComponent;
-use crate::config::AppConfig;
-use crate::config::MultiOn;
-use crate::mx100qp::Channel;
-use crate::mx100qp::MultiChannelOn;
-use crate::mx100qp::Mx100qp;
-use crate::mx100qp::VoltageTracking;
-use crate::mx100qp::auto_vrange;
+use crate::supplies::{ChannelState, Event, Supplies, SupplyState};
 use dioxus::prelude::*;
 use futures::StreamExt;
+use futures::future::{Either, select};
 use log::{error, info};
+use power_supply_control::config::AppConfig;
+use power_supply_control::config::ChannelConfig;
+use power_supply_control::config::MultiOff;
+use power_supply_control::config::MultiOn;
+use power_supply_control::config::PowerSupplyConfig;
+use power_supply_control::config::SetupStore;
+use power_supply_control::models::Features;
+use power_supply_control::models::Model;
+use power_supply_control::mx100qp::Channel;
+use power_supply_control::mx100qp::ErrorRegisters;
+use power_supply_control::mx100qp::LimitEventStatus;
+use power_supply_control::mx100qp::MultiChannelOff;
+use power_supply_control::mx100qp::MultiChannelOn;
+use power_supply_control::mx100qp::Mx100qp;
+use power_supply_control::mx100qp::ProtocolError;
+use power_supply_control::mx100qp::VoltageTrackingState;
+use tokio::sync::oneshot;
 
 use crate::components::channel::ChannelComponent;
 use crate::components::editable_text::EditableTextComponent;
+use crate::components::setup_stores::SetupStoresComponent;
+use crate::energy::Energy;
+use crate::history::{self, ChannelHistory};
+use crate::stats::ChannelStats;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ChannelSelection {
     AllChannels,
     Channel(u8),
@@ -26,25 +46,187 @@ pub enum ChannelSelection {
 #[derive(Debug)]
 pub enum PowerSupplyAction {
     On(ChannelSelection),
-    Off(ChannelSelection),
+    /// Switches outputs off, interrupting a running poll. Holds the time of the request.
+    Off(ChannelSelection, Instant),
     SetVoltage(u8, f32),
     SetCurrent(u8, f32),
     RenameChannel(u8, String),
     SetMultiChannel(u8, MultiChannelOn),
+    SetMultiChannelOff(u8, MultiChannelOff),
     SetVRange(u8, u8),
     SetAutoVRange(u8, bool),
-    SetVoltageTracking(VoltageTracking),
+    SetVoltageTracking(u8),
     SetOvervoltageTrip(u8, Option<f32>),
     SetOvercurrentTrip(u8, Option<f32>),
     Reconfigure,
     TripReset,
+    SaveSetup(ChannelSelection, u8, String),
+    RecallSetup(ChannelSelection, u8),
+    /// Raw line from a passthrough client with its error registers, answered with the
+    /// reply to its queries and the updated registers.
+    Passthrough(
+        String,
+        ErrorRegisters,
+        oneshot::Sender<(ErrorRegisters, Result<Option<String>, String>)>,
+    ),
+}
+
+impl PowerSupplyAction {
+    /// Returns true for actions which must not wait for a running poll.
+    fn is_priority(&self) -> bool {
+        matches!(self, PowerSupplyAction::Off(..))
+    }
 }
 
 struct PowerSupply {
     name: String,
     channels: Vec<Channel>,
     connected: bool,
-    voltage_tracking: Option<VoltageTracking>,
+    model: Option<&'static Model>,
+    voltage_tracking: Option<u8>,
+    error: Option<String>,
+    rejected: Vec<String>,
+    /// Duration of the last poll.
+    poll_time: Option<Duration>,
+}
+
+/// Number of most recent rejected commands shown.
+const MAX_REJECTED: usize = 5;
+
+fn report_error(state: &mut Signal<PowerSupply>, err: &ProtocolError) {
+    let mut state = state.write();
+    if let ProtocolError::Rejected { .. } = err {
+        state.rejected.push(err.to_string());
+        let excess = state.rejected.len().saturating_sub(MAX_REJECTED);
+        state.rejected.drain(..excess);
+    } else {
+        state.error = Some(err.to_string());
+    }
+}
+
+fn channel_states(
+    channels: &[Channel],
+    config: &PowerSupplyConfig,
+    stats: &[ChannelStats],
+) -> Vec<ChannelState> {
+    channels
+        .iter()
+        .map(|channel| {
+            let name = config
+                .channels
+                .get(channel.index as usize)
+                .map_or("", |conf| conf.name.as_str());
+            let stats = stats
+                .get(channel.index as usize)
+                .and_then(ChannelStats::summary);
+            ChannelState::new(channel, name, stats)
+        })
+        .collect()
+}
+
+/// State published to the API.
+fn snapshot(
+    id: &str,
+    state: &PowerSupply,
+    config: &PowerSupplyConfig,
+    stats: &[ChannelStats],
+) -> SupplyState {
+    SupplyState {
+        id: id.to_string(),
+        name: state.name.clone(),
+        model: state.model.map(|model| model.name),
+        connected: state.connected,
+        voltage_tracking: state.voltage_tracking,
+        error: state.error.clone(),
+        rejected: state.rejected.clone(),
+        poll_duration: state.poll_time.map(|time| time.as_secs_f64()),
+        channels: channel_states(&state.channels, config, stats),
+    }
+}
+
+/// Publishes new readings to telemetry together with trips not reported by the previous ones.
+fn publish_readings(
+    supplies: &Supplies,
+    id: &str,
+    state: &PowerSupply,
+    channels: &[Channel],
+    config: &PowerSupplyConfig,
+    stats: &[ChannelStats],
+    started: Instant,
+) {
+    let channel_states = channel_states(channels, config, stats);
+    for (channel, channel_state) in channels.iter().zip(&channel_states) {
+        let previous = state
+            .channels
+            .get(channel.index as usize)
+            .map_or(LimitEventStatus::empty(), |previous| previous.status);
+        let trips = channel.status & LimitEventStatus::TRIPS;
+        for (trip, _) in trips.difference(previous).iter_names() {
+            let event = Event::Trip {
+                channel: channel_state.channel,
+                channel_name: channel_state.name.clone(),
+                trip,
+            };
+            supplies.publish(id, &state.name, event);
+        }
+    }
+
+    let event = Event::Readings {
+        channels: channel_states,
+        started: Some(started),
+    };
+    supplies.publish(id, &state.name, event);
+}
+
+async fn setup(
+    appconfig: &mut Signal<AppConfig>,
+    port: &mut Mx100qp,
+    state: &mut Signal<PowerSupply>,
+    id: &str,
+) -> Result<(), ProtocolError> {
+    state.write().voltage_tracking = Some(port.get_voltage_tracking().await?);
+
+    for ch in 0..port.model.channels {
+        let config = channel_config(&mut appconfig.write(), id, ch)?.clone();
+        if port.model.supports(Features::MULTI_ON) {
+            let behaviour = match config.multi_on.enabled {
+                true => MultiChannelOn::Delay(config.multi_on.delay_ms),
+                false => MultiChannelOn::Disabled,
+            };
+            port.multichannel_on_setup(ch, behaviour).await?;
+        }
+        if port.model.supports(Features::MULTI_OFF) {
+            let behaviour = match config.multi_off.enabled {
+                true => MultiChannelOff::Delay(config.multi_off.delay_ms),
+                false => MultiChannelOff::Disabled,
+            };
+            port.multichannel_off_setup(ch, behaviour).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Configuration of the channel, actions for a channel missing in it are rejected.
+fn channel_config<'a>(
+    appconfig: &'a mut AppConfig,
+    id: &str,
+    ch: u8,
+) -> Result<&'a mut ChannelConfig, ProtocolError> {
+    appconfig
+        .power_supply_channel(id, ch)
+        .ok_or(ProtocolError::UnknownChannel(ch + 1))
+}
+
+/// Updates channel configuration with values read back from the device.
+fn reconcile_channel(config: &mut ChannelConfig, channel: &Channel) {
+    if channel.voltage_tracking != VoltageTrackingState::Slave {
+        config.voltage = channel.voltage.set;
+    }
+    config.current = channel.current.set;
+    config.vrange = channel.vrange;
+    config.overvoltage_trip = channel.overvoltage_trip;
+    config.overcurrent_trip = channel.overcurrent_trip;
 }
 
 async fn handle_action(
@@ -53,26 +235,28 @@ async fn handle_action(
     port: &mut Mx100qp,
     state: &mut Signal<PowerSupply>,
     id: &str,
-) -> Result<(), std::io::Error> {
+) -> Result<(), ProtocolError> {
     match msg {
         PowerSupplyAction::On(channels) => match channels {
             ChannelSelection::AllChannels => port.all_channel_on().await,
             ChannelSelection::Channel(ch) => port.channel_on(ch).await,
         },
-        PowerSupplyAction::Off(channels) => match channels {
+        PowerSupplyAction::Off(channels, _) => match channels {
             ChannelSelection::AllChannels => port.all_channel_off().await,
             ChannelSelection::Channel(ch) => port.channel_off(ch).await,
         },
         PowerSupplyAction::SetVoltage(ch, new_voltage) => {
             let mut conf = appconfig.write();
-            let channel_conf = &mut conf.power_supply_channel(id, ch);
+            let channel_conf = channel_config(&mut conf, id, ch)?;
             channel_conf.voltage = new_voltage;
 
             if channel_conf.auto_vrange
-                && let Some(vrange) = auto_vrange(ch, channel_conf.voltage, channel_conf.current)
+                && let Some(vrange) =
+                    port.model
+                        .auto_vrange(ch, channel_conf.voltage, channel_conf.current)
             {
                 channel_conf.vrange = vrange;
-                port.set_vrange(ch, channel_conf.vrange).await.unwrap();
+                port.set_vrange(ch, channel_conf.vrange).await?;
             }
 
             conf.save();
@@ -80,23 +264,23 @@ async fn handle_action(
         }
         PowerSupplyAction::SetCurrent(ch, new_current) => {
             let mut conf = appconfig.write();
-            let channel_conf = &mut conf.power_supply_channel(id, ch);
+            let channel_conf = channel_config(&mut conf, id, ch)?;
             channel_conf.current = new_current;
 
             if channel_conf.auto_vrange
-                && let Some(vrange) = auto_vrange(ch, channel_conf.voltage, channel_conf.current)
+                && let Some(vrange) =
+                    port.model
+                        .auto_vrange(ch, channel_conf.voltage, channel_conf.current)
             {
                 channel_conf.vrange = vrange;
-                port.set_vrange(ch, channel_conf.vrange).await.unwrap();
+                port.set_vrange(ch, channel_conf.vrange).await?;
             }
 
             conf.save();
             port.set_current(ch, new_current).await
         }
         PowerSupplyAction::RenameChannel(ch, new_name) => {
-            appconfig
-                .write()
-                .power_supply_channel(id, ch)
+            channel_config(&mut appconfig.write(), id, ch)?
                 .name
                 .clone_from(&new_name);
             appconfig.write().save();
@@ -105,14 +289,12 @@ async fn handle_action(
         PowerSupplyAction::SetMultiChannel(channel, behaviour) => {
             match behaviour {
                 MultiChannelOn::Disabled => {
-                    appconfig
-                        .write()
-                        .power_supply_channel(id, channel)
+                    channel_config(&mut appconfig.write(), id, channel)?
                         .multi_on
                         .enabled = false;
                 }
                 MultiChannelOn::Delay(delay_ms) => {
-                    appconfig.write().power_supply_channel(id, channel).multi_on = MultiOn {
+                    channel_config(&mut appconfig.write(), id, channel)?.multi_on = MultiOn {
                         enabled: true,
                         delay_ms,
                     };
@@ -122,80 +304,163 @@ async fn handle_action(
 
             port.multichannel_on_setup(channel, behaviour).await
         }
+        PowerSupplyAction::SetMultiChannelOff(channel, behaviour) => {
+            match behaviour {
+                MultiChannelOff::Disabled => {
+                    channel_config(&mut appconfig.write(), id, channel)?
+                        .multi_off
+                        .enabled = false;
+                }
+                MultiChannelOff::Delay(delay_ms) => {
+                    channel_config(&mut appconfig.write(), id, channel)?.multi_off = MultiOff {
+                        enabled: true,
+                        delay_ms,
+                    };
+                }
+            };
+            appconfig.write().save();
+
+            port.multichannel_off_setup(channel, behaviour).await
+        }
         PowerSupplyAction::SetVRange(channel, vrange) => {
-            appconfig.write().power_supply_channel(id, channel).vrange = vrange;
+            channel_config(&mut appconfig.write(), id, channel)?.vrange = vrange;
             appconfig.write().save();
             port.set_vrange(channel, vrange).await
         }
         PowerSupplyAction::SetAutoVRange(channel, enable) => {
-            appconfig
-                .write()
-                .power_supply_channel(id, channel)
-                .auto_vrange = enable;
+            channel_config(&mut appconfig.write(), id, channel)?.auto_vrange = enable;
             appconfig.write().save();
             Ok(())
         }
         PowerSupplyAction::SetVoltageTracking(config) => {
-            appconfig.write().power_supply_mut(id).voltage_tracking = config.clone().into();
+            if let Some(supply) = appconfig.write().power_supply_mut(id) {
+                supply.voltage_tracking = config;
+            }
             appconfig.write().save();
-            port.set_voltage_tracking(config).await.unwrap();
-            state.write().voltage_tracking = Some(port.get_voltage_tracking().await.unwrap());
+            port.set_voltage_tracking(config).await?;
+            state.write().voltage_tracking = Some(port.get_voltage_tracking().await?);
             Ok(())
         }
         PowerSupplyAction::SetOvervoltageTrip(channel, voltage) => {
-            appconfig
-                .write()
-                .power_supply_channel(id, channel)
-                .overvoltage_trip = voltage;
+            channel_config(&mut appconfig.write(), id, channel)?.overvoltage_trip = voltage;
             appconfig.write().save();
             port.set_overvoltage_trip(channel, voltage).await
         }
         PowerSupplyAction::SetOvercurrentTrip(channel, current) => {
-            appconfig
-                .write()
-                .power_supply_channel(id, channel)
-                .overcurrent_trip = current;
+            channel_config(&mut appconfig.write(), id, channel)?.overcurrent_trip = current;
             appconfig.write().save();
             port.set_overcurrent_trip(channel, current).await
         }
         PowerSupplyAction::Reconfigure => {
-            port.all_channel_off().await.unwrap();
-
-            let power_supply = appconfig.write().power_supply_mut(id).clone();
-
-            port.set_voltage_tracking(
-                VoltageTracking::try_from(power_supply.voltage_tracking).unwrap(),
-            )
-            .await
-            .unwrap();
-
-            state.write().voltage_tracking = Some(port.get_voltage_tracking().await.unwrap());
-
-            for (ch, channel_config) in power_supply.channels.iter().enumerate() {
-                let ch = ch as u8;
-                port.set_vrange(ch, channel_config.vrange).await.unwrap();
-                port.set_voltage(ch, channel_config.voltage).await.unwrap();
-                port.set_current(ch, channel_config.current).await.unwrap();
-                port.set_overvoltage_trip(ch, channel_config.overvoltage_trip)
-                    .await
-                    .unwrap();
-                port.set_overcurrent_trip(ch, channel_config.overcurrent_trip)
-                    .await
-                    .unwrap();
-                port.multichannel_on_setup(
-                    ch,
-                    match channel_config.multi_on.enabled {
-                        true => MultiChannelOn::Delay(channel_config.multi_on.delay_ms),
-                        false => MultiChannelOn::Disabled,
-                    },
-                )
-                .await
-                .unwrap();
+            let Some(power_supply) = appconfig.read().power_supply(id).cloned() else {
+                return Ok(());
+            };
+            port.apply_config(&power_supply).await?;
+            state.write().voltage_tracking = Some(port.get_voltage_tracking().await?);
+            Ok(())
+        }
+        PowerSupplyAction::TripReset => port.trip_reset().await,
+        PowerSupplyAction::Passthrough(line, mut errors, reply) => {
+            let result = port.passthrough(&line, &mut errors).await;
+            let _ = reply.send((
+                errors,
+                result.as_ref().cloned().map_err(ToString::to_string),
+            ));
+            match result {
+                // errors of the client are reported to the client only
+                Err(err) if !err.is_recoverable() => Err(err),
+                _ => Ok(()),
+            }
+        }
+        PowerSupplyAction::SaveSetup(channels, store, label) => {
+            let channel = match channels {
+                ChannelSelection::AllChannels => {
+                    port.save_setup(store).await?;
+                    None
+                }
+                ChannelSelection::Channel(ch) => {
+                    port.save_channel_setup(ch, store).await?;
+                    Some(ch)
+                }
+            };
+
+            let mut conf = appconfig.write();
+            let Some(config) = conf.power_supply_mut(id) else {
+                return Ok(());
+            };
+            let stores = &mut config.setup_stores;
+            stores.retain(|existing| existing.channel != channel || existing.store != store);
+            stores.push(SetupStore {
+                channel,
+                store,
+                label,
+            });
+            stores.sort_by_key(|existing| (existing.channel, existing.store));
+            conf.save();
+            Ok(())
+        }
+        PowerSupplyAction::RecallSetup(channels, store) => {
+            match channels {
+                ChannelSelection::AllChannels => port.recall_setup(store).await?,
+                ChannelSelection::Channel(ch) => port.recall_channel_setup(ch, store).await?,
+            };
+
+            let voltage_tracking = port.get_voltage_tracking().await?;
+            let channels_read = port.read_channels().await?;
+
+            let mut conf = appconfig.write();
+            for channel in channels_read.iter() {
+                if channels == ChannelSelection::AllChannels
+                    || channels == ChannelSelection::Channel(channel.index)
+                {
+                    reconcile_channel(channel_config(&mut conf, id, channel.index)?, channel);
+                }
             }
+            if channels == ChannelSelection::AllChannels
+                && let Some(config) = conf.power_supply_mut(id)
+            {
+                config.voltage_tracking = voltage_tracking;
+            }
+            conf.save();
 
+            state.write().voltage_tracking = Some(voltage_tracking);
+            state.write().channels = channels_read;
             Ok(())
         }
-        PowerSupplyAction::TripReset => port.trip_reset().await,
+    }
+}
+
+/// Handles the action and reports its error, returns false if the connection has to be reopened.
+async fn dispatch(
+    msg: PowerSupplyAction,
+    appconfig: &mut Signal<AppConfig>,
+    port: &mut Mx100qp,
+    state: &mut Signal<PowerSupply>,
+    id: &str,
+) -> bool {
+    info!("{msg:?}");
+    let requested = match &msg {
+        PowerSupplyAction::Off(_, requested) => Some((*requested, requested.elapsed())),
+        _ => None,
+    };
+
+    let res = handle_action(msg, appconfig, port, state, id).await;
+
+    if let Some((requested, dispatched)) = requested {
+        info!(
+            "Off sent {} ms after request, confirmed after {} ms",
+            dispatched.as_millis(),
+            requested.elapsed().as_millis()
+        );
+    }
+
+    match res {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Error: {err}");
+            report_error(state, &err);
+            err.is_recoverable()
+        }
     }
 }
 
@@ -204,90 +469,220 @@ pub fn PowerSupplyComponent(id: String) -> Element {
     let mut show_delete_modal = use_signal(|| false);
     let edit_mode = use_context::<Signal<EditMode>>();
     let mut appconfig = use_context::<Signal<AppConfig>>();
-    let config = appconfig().power_supply(&id).clone();
+    // rendered once more after the supply is removed from the configuration
+    let Some(config) = appconfig().power_supply(&id).cloned() else {
+        return rsx! {};
+    };
     let mut state = use_signal(|| PowerSupply {
         name: config.name.clone(),
         channels: Vec::new(),
         connected: false,
+        model: None,
         voltage_tracking: None,
+        error: None,
+        rejected: Vec::new(),
+        poll_time: None,
     });
 
+    let mut history = use_signal(Vec::<ChannelHistory>::new);
+    let mut energy = use_signal(Vec::<Energy>::new);
+    let mut stats = use_signal(Vec::<ChannelStats>::new);
+
     let voltage_tracking = config.voltage_tracking;
     let id1 = id.clone();
     let id2 = id.clone();
+    let id3 = id.clone();
+    #[cfg(feature = "datalog")]
+    let id4 = id.clone();
     let name = config.name.clone();
     let channels = config.channels.clone();
 
-    let voltage_trackings = ["V1 V2 V3 V4", "V1=V2 V3 V4", "V1 V2 V3=V4", "V1=V2 V3=V4"];
+    let model = state.read().model.filter(|_| state.read().connected);
 
     let mut errors = Vec::new();
-    if state.read().voltage_tracking
-        != Some(VoltageTracking::try_from(config.voltage_tracking).unwrap())
+    if let Some(model) = model
+        && !model.tracking.is_empty()
+        && state.read().voltage_tracking != Some(voltage_tracking)
     {
         errors.push(format!(
             "Different voltage tracking {:?} is set.",
-            match &state.read().voltage_tracking {
-                None => "",
-                Some(val) => voltage_trackings[Into::<u8>::into(val.clone()) as usize],
-            }
+            state
+                .read()
+                .voltage_tracking
+                .and_then(|config| model.tracking_mode(config))
+                .map_or("", |mode| mode.label)
         ));
     }
 
+    let supplies = use_context::<Supplies>();
+    let telemetry = supplies.clone();
     let sync_task = use_coroutine(move |mut rx: UnboundedReceiver<PowerSupplyAction>| {
         let id = id.clone();
         let mut appconfig = appconfig;
+        let telemetry = telemetry.clone();
         async move {
+            // actions received during a poll or not yet handled when the connection
+            // failed, kept for the reconnect like the ones still queued in the channel
+            let mut deferred = VecDeque::new();
             loop {
+                if state.read().connected {
+                    let event = Event::Disconnected {
+                        error: state.read().error.clone(),
+                    };
+                    telemetry.publish(&id, &state.read().name, event);
+                }
                 state.write().connected = false;
-                let config = appconfig.write().power_supply_mut(&id).clone();
-                let port = Mx100qp::open(&config.clone()).await;
-
-                if let Err(err) = port {
-                    error!("failed to open port: {err:?}");
-                    tokio::time::sleep(Duration::from_millis(1000)).await;
-                    continue;
+                energy.write().iter_mut().for_each(Energy::interrupt);
+                let Some(config) = appconfig.read().power_supply(&id).cloned() else {
+                    return;
+                };
+                let mut port = match Mx100qp::open(&config).await {
+                    Ok(port) => port,
+                    Err(err) => {
+                        error!("failed to open port: {err}");
+                        state.write().error = Some(err.to_string());
+                        tokio::time::sleep(Duration::from_millis(1000)).await;
+                        continue;
+                    }
+                };
+
+                state.write().model = Some(port.model);
+                if appconfig
+                    .write()
+                    .power_supply_mut(&id)
+                    .is_some_and(|config| config.adapt_to_model(port.model))
+                {
+                    info!("Configuration adapted to {}", port.model.name);
+                    appconfig.write().save();
                 }
-                let mut port = port.unwrap();
-                state.write().voltage_tracking = Some(port.get_voltage_tracking().await.unwrap());
 
-                for ch in 0..4 {
-                    let multi_on = appconfig
-                        .write()
-                        .power_supply_channel(&id, ch)
-                        .multi_on
-                        .clone();
-                    let behaviour = match multi_on.enabled {
-                        true => MultiChannelOn::Delay(multi_on.delay_ms),
-                        false => MultiChannelOn::Disabled,
-                    };
-
-                    port.multichannel_on_setup(ch, behaviour).await.unwrap();
+                if let Err(err) = setup(&mut appconfig, &mut port, &mut state, &id).await {
+                    error!("failed to setup power supply: {err}");
+                    report_error(&mut state, &err);
+                    if !err.is_recoverable() {
+                        continue;
+                    }
                 }
 
                 state.write().connected = true;
-
-                loop {
-                    if let Ok(Some(msg)) =
-                        tokio::time::timeout(Duration::from_millis(100), rx.next()).await
+                state.write().error = None;
+                let event = Event::Connected {
+                    model: port.model.name,
+                };
+                telemetry.publish(&id, &state.read().name, event);
+
+                let schedule = config.poll.clone();
+                let mut polls: u32 = 0;
+                'connected: loop {
+                    // deferred actions are handled right away instead of after the interval
+                    if deferred.is_empty()
+                        && let Ok(Some(msg)) = tokio::time::timeout(
+                            Duration::from_millis(schedule.interval_ms),
+                            rx.next(),
+                        )
+                        .await
                     {
-                        info!("{msg:?}");
-                        let res =
-                            handle_action(msg, &mut appconfig, &mut port, &mut state, &id).await;
+                        deferred.push_back(msg);
+                    }
+                    // actions queued before the poll starts are handled before it, so that
+                    // its readings reflect them
+                    let started = loop {
+                        let started = Instant::now();
+                        while let Ok(msg) = rx.try_recv() {
+                            deferred.push_back(msg);
+                        }
+                        let Some(msg) = deferred.pop_front() else {
+                            break started;
+                        };
+                        if !dispatch(msg, &mut appconfig, &mut port, &mut state, &id).await {
+                            break 'connected;
+                        }
+                    };
+                    let read_settings = polls.is_multiple_of(schedule.settings_every.max(1));
+                    polls = polls.wrapping_add(1);
+                    let result = {
+                        let mut poll = pin!(port.poll(read_settings));
+                        loop {
+                            match select(poll.as_mut(), rx.next()).await {
+                                Either::Left((result, _)) => break Ok(result),
+                                Either::Right((Some(msg), _)) if msg.is_priority() => {
+                                    break Err(msg);
+                                }
+                                Either::Right((Some(msg), _)) => deferred.push_back(msg),
+                                Either::Right((None, _)) => return,
+                            }
+                        }
+                    };
 
-                        if let Err(err) = res {
-                            error!("Error: {err}");
-                            break;
+                    match result {
+                        Ok(Ok(new)) => {
+                            let now = Instant::now();
+                            history::record_all(&mut stats.write(), now, &new);
+                            if let Some(config) = appconfig.read().power_supply(&id) {
+                                publish_readings(
+                                    &telemetry,
+                                    &id,
+                                    &state.read(),
+                                    &new,
+                                    config,
+                                    &stats.read(),
+                                    started,
+                                );
+                            }
+                            history::record_all(&mut history.write(), now, &new);
+                            history::record_all(&mut energy.write(), now, &new);
+                            let mut state = state.write();
+                            state.channels = new;
+                            state.poll_time = Some(started.elapsed());
+                        }
+                        Ok(Err(err)) => {
+                            error!("failed to read channels: {err}");
+                            report_error(&mut state, &err);
+                            if !err.is_recoverable() {
+                                break;
+                            }
+                        }
+                        Err(msg) => {
+                            info!("Poll interrupted");
+                            if !dispatch(msg, &mut appconfig, &mut port, &mut state, &id).await {
+                                break;
+                            }
                         }
-                    }
-                    match port.read_channels().await {
-                        Ok(new) => state.write().channels = new,
-                        Err(_) => break,
                     };
                 }
             }
         }
     });
 
+    use_hook({
+        let supplies = supplies.clone();
+        let id = id3.clone();
+        move || {
+            if let Some(config) = appconfig.read().power_supply(&id) {
+                let state = snapshot(&id, &state.read(), config, &stats.read());
+                supplies.register(state, sync_task.tx());
+            }
+        }
+    });
+    use_effect({
+        let supplies = supplies.clone();
+        let id = id3.clone();
+        move || {
+            // the configuration is gone before the component when the supply is removed
+            if let Some(config) = appconfig.read().power_supply(&id) {
+                supplies.update(snapshot(&id, &state.read(), config, &stats.read()));
+            }
+        }
+    });
+    use_drop(move || supplies.unregister(&id3));
+
+    #[cfg(feature = "datalog")]
+    let data_log = rsx! {
+        DataLogComponent { id: id4.clone(), name: state.read().name.clone() }
+    };
+    #[cfg(not(feature = "datalog"))]
+    let data_log = rsx! {};
+
     rsx! {
         div { class: "card mb-1",
             div { class: "card-header d-flex gap-3",
@@ -295,7 +690,9 @@ pub fn PowerSupplyComponent(id: String) -> Element {
                     EditableTextComponent {
                         onsubmit: move |new_name: String| {
                             state.write().name.clone_from(&new_name);
-                            appconfig.write().power_supply_mut(&id1).name.clone_from(&new_name);
+                            if let Some(config) = appconfig.write().power_supply_mut(&id1) {
+                                config.name.clone_from(&new_name);
+                            }
                             appconfig.write().save();
                         },
                         disabled: !edit_mode.read().0,
@@ -310,7 +707,7 @@ pub fn PowerSupplyComponent(id: String) -> Element {
                         }
                     }
                 }
-                if state.read().connected {
+                if let Some(model) = model {
                     div { class: "d-flex gap-1",
 
                         if edit_mode.read().0 {
@@ -321,40 +718,61 @@ pub fn PowerSupplyComponent(id: String) -> Element {
                             }
                         }
 
+                        if let Some(poll_time) = state.read().poll_time {
+                            span {
+                                class: "small text-muted align-self-center",
+                                title: "Duration of the last poll",
+                                "{poll_time.as_millis()} ms"
+                            }
+                        }
+
+                        {data_log}
+
                         button {
                             class: "btn btn-sm btn-secondary",
                             onclick: move |_| sync_task.send(PowerSupplyAction::TripReset),
                             "Trip rst"
                         }
 
-                        if edit_mode.read().0 {
+                        if edit_mode.read().0 && !model.tracking.is_empty() {
                             select {
                                 class: "form-control form-control-sm w-auto",
                                 onchange: move |evt| {
-                                    sync_task
-                                        .send(
-                                            PowerSupplyAction::SetVoltageTracking(
-                                                evt.data.value().parse::<u8>().unwrap().try_into().unwrap(),
-                                            ),
-                                        )
+                                    if let Ok(config) = evt.data.value().parse::<u8>() {
+                                        sync_task.send(PowerSupplyAction::SetVoltageTracking(config))
+                                    }
                                 },
-                                for (i , label) in voltage_trackings.iter().enumerate() {
+                                for mode in model.tracking.iter() {
                                     option {
-                                        value: "{i}",
-                                        selected: voltage_tracking == i as u8,
-                                        {label}
+                                        value: "{mode.config}",
+                                        selected: voltage_tracking == mode.config,
+                                        {mode.label}
                                     }
                                 }
                             }
                         }
 
                         div { class: "input-group input-group-sm w-auto",
-                            span { class: "input-group-text", "Delayed MultiON" }
-                            for (channel , channel_conf) in channels.iter().enumerate() {
-                                ChannelDelayComponent {
-                                    channel: channel as u8,
-                                    enabled: channel_conf.multi_on.enabled,
-                                    delay_ms: channel_conf.multi_on.delay_ms,
+                            if model.supports(Features::MULTI_ON) {
+                                span { class: "input-group-text", "Delayed MultiON" }
+                                for (channel , channel_conf) in channels.iter().enumerate() {
+                                    ChannelDelayComponent {
+                                        channel: channel as u8,
+                                        off: false,
+                                        enabled: channel_conf.multi_on.enabled,
+                                        delay_ms: channel_conf.multi_on.delay_ms,
+                                    }
+                                }
+                            }
+                            if model.supports(Features::MULTI_OFF) {
+                                span { class: "input-group-text", "Delayed MultiOFF" }
+                                for (channel , channel_conf) in channels.iter().enumerate() {
+                                    ChannelDelayComponent {
+                                        channel: channel as u8,
+                                        off: true,
+                                        enabled: channel_conf.multi_off.enabled,
+                                        delay_ms: channel_conf.multi_off.delay_ms,
+                                    }
                                 }
                             }
                             button {
@@ -364,16 +782,43 @@ pub fn PowerSupplyComponent(id: String) -> Element {
                             }
                             button {
                                 class: "btn btn-sm btn-danger",
-                                onclick: move |_| sync_task.send(PowerSupplyAction::Off(ChannelSelection::AllChannels)),
+                                onclick: move |_| {
+                                    sync_task
+                                        .send(PowerSupplyAction::Off(ChannelSelection::AllChannels, Instant::now()))
+                                },
                                 "OFF"
                             }
                         }
                     }
                 }
             }
-            if state.read().connected {
+            if let Some(model) = model {
                 div { class: "card-body",
 
+                    if let Some(error) = &state.read().error {
+                        div { class: "alert alert-warning d-flex mt-1 p-1 mb-1",
+                            div { class: "flex-grow-1", {error.clone()} }
+                            button {
+                                class: "btn-close",
+                                onclick: move |_| state.write().error = None,
+                            }
+                        }
+                    }
+
+                    if !state.read().rejected.is_empty() {
+                        div { class: "alert alert-danger d-flex mt-1 p-0 mb-1",
+                            ul { class: "flex-grow-1 mt-1 mb-1",
+                                for rejected in state.read().rejected.iter() {
+                                    li { {rejected.clone()} }
+                                }
+                            }
+                            button {
+                                class: "btn-close m-1",
+                                onclick: move |_| state.write().rejected.clear(),
+                            }
+                        }
+                    }
+
                     if !errors.is_empty() {
                         div { class: "alert alert-danger mt-1 p-0 mb-1",
                             ul { class: "mt-1 mb-1",
@@ -389,12 +834,31 @@ pub fn PowerSupplyComponent(id: String) -> Element {
                             ChannelComponent {
                                 channel: channel.clone(),
                                 config: channels[i].clone(),
+                                model,
+                                history,
+                                energy,
+                                stats,
                             }
                         }
                     }
+
+                    if edit_mode.read().0
+                        && model.features.intersects(Features::SETUP_STORES | Features::CHANNEL_SETUP_STORES)
+                    {
+                        SetupStoresComponent {
+                            model,
+                            stores: config.setup_stores.clone(),
+                            channels: channels.iter().map(|ch| ch.name.clone()).collect::<Vec<_>>(),
+                        }
+                    }
                 }
             } else {
-                div { class: "text-center", "Device not connected." }
+                div { class: "text-center",
+                    "Device not connected."
+                    if let Some(error) = &state.read().error {
+                        div { class: "text-danger small", {error.clone()} }
+                    }
+                }
             }
         }
 
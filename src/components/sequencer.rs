@@ -0,0 +1,85 @@
+use dioxus::prelude::*;
+use log::{error, info};
+use power_supply_control::config::AppConfig;
+
+use crate::{sequencer, supplies::Supplies};
+
+/// Buttons running the configured power sequences, aborting one powers down what it switched on.
+#[component]
+pub fn SequencerComponent() -> Element {
+    let supplies = use_context::<Supplies>();
+    let appconfig = use_context::<Signal<AppConfig>>();
+    let mut running = use_signal(|| None::<(String, Task)>);
+    let mut step = use_signal(|| 0usize);
+    let mut failure = use_signal(|| None::<String>);
+
+    let sequences = appconfig.read().data.sequences.clone();
+    if sequences.is_empty() {
+        return rsx! {};
+    }
+
+    let status = running.read().as_ref().map(|(name, _)| {
+        let total = sequences
+            .iter()
+            .find(|sequence| sequence.name == *name)
+            .map_or(0, |sequence| sequence.steps.len());
+        format!("{name}: step {}/{total}", step() + 1)
+    });
+
+    rsx! {
+        div { class: "d-flex gap-2 me-3 align-items-center",
+            if let Some(status) = status {
+                span { class: "small", {status} }
+                button {
+                    class: "btn btn-sm btn-danger",
+                    onclick: move |_| {
+                        if let Some((name, task)) = running.take() {
+                            info!("Sequence {name} aborted");
+                            // dropping the sequence powers down the channels it switched on
+                            task.cancel();
+                        }
+                    },
+                    "Abort"
+                }
+            } else {
+                for sequence in sequences {
+                    button {
+                        class: "btn btn-sm btn-outline-primary",
+                        onclick: {
+                            let supplies = supplies.clone();
+                            move |_| {
+                                let supplies = supplies.clone();
+                                let sequence = sequence.clone();
+                                let name = sequence.name.clone();
+                                failure.set(None);
+                                step.set(0);
+                                info!("Sequence {} started", sequence.name);
+                                let task = spawn(async move {
+                                    let result = sequencer::run(
+                                            &supplies,
+                                            &sequence.steps,
+                                            |number| step.set(number),
+                                        )
+                                        .await;
+                                    match result {
+                                        Ok(()) => info!("Sequence {} finished", sequence.name),
+                                        Err(err) => {
+                                            error!("Sequence {} failed: {err}", sequence.name);
+                                            failure.set(Some(format!("{}: {err}", sequence.name)));
+                                        }
+                                    }
+                                    running.set(None);
+                                });
+                                running.set(Some((name, task)));
+                            }
+                        },
+                        {sequence.name.clone()}
+                    }
+                }
+            }
+            if let Some(failure) = failure() {
+                span { class: "small text-danger", {failure} }
+            }
+        }
+    }
+}
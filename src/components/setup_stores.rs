@@ -0,0 +1,120 @@
+use dioxus::prelude::*;
+
+use crate::components::power_supply::{ChannelSelection, PowerSupplyAction};
+use power_supply_control::{
+    config::SetupStore,
+    models::{Features, Model},
+};
+
+fn parse_selection(value: &str) -> ChannelSelection {
+    match value.parse() {
+        Ok(ch) => ChannelSelection::Channel(ch),
+        Err(_) => ChannelSelection::AllChannels,
+    }
+}
+
+#[component]
+pub fn SetupStoresComponent(
+    model: &'static Model,
+    stores: Vec<SetupStore>,
+    channels: Vec<String>,
+) -> Element {
+    let power_supply_action = use_coroutine_handle::<PowerSupplyAction>();
+    let all_channels = model.supports(Features::SETUP_STORES);
+    let single_channel = model.supports(Features::CHANNEL_SETUP_STORES);
+    let mut selection = use_signal(move || match all_channels {
+        true => ChannelSelection::AllChannels,
+        false => ChannelSelection::Channel(0),
+    });
+    let mut store = use_signal(|| 0u8);
+    let mut label = use_signal(String::new);
+
+    let channel_name = |channel: Option<u8>| match channel {
+        None => "All channels".to_string(),
+        Some(ch) => channels
+            .get(ch as usize)
+            .cloned()
+            .unwrap_or(format!("CH{}", ch + 1)),
+    };
+
+    rsx! {
+        div { class: "input-group input-group-sm mt-1",
+            span { class: "input-group-text", "Setup store" }
+            select {
+                class: "form-control form-control-sm",
+                onchange: move |evt| selection.set(parse_selection(&evt.value())),
+                if all_channels {
+                    option { value: "all", "All channels" }
+                }
+                if single_channel {
+                    for (i , name) in channels.iter().enumerate() {
+                        option { value: "{i}", "{name}" }
+                    }
+                }
+            }
+            input {
+                class: "form-control form-control-sm text-end",
+                r#type: "number",
+                min: 0,
+                max: model.setup_stores - 1,
+                value: "{store}",
+                autocomplete: "off",
+                oninput: move |evt| {
+                    if let Ok(value) = evt.value().parse::<u8>()
+                        && value < model.setup_stores
+                    {
+                        store.set(value);
+                    }
+                },
+            }
+            input {
+                class: "form-control form-control-sm",
+                placeholder: "label",
+                value: "{label}",
+                autocomplete: "off",
+                oninput: move |evt| label.set(evt.value()),
+            }
+            button {
+                class: "btn btn-sm btn-outline-secondary",
+                onclick: move |_| {
+                    power_supply_action
+                        .send(PowerSupplyAction::SaveSetup(selection(), store(), label()));
+                },
+                "Save"
+            }
+            button {
+                class: "btn btn-sm btn-outline-secondary",
+                onclick: move |_| {
+                    power_supply_action.send(PowerSupplyAction::RecallSetup(selection(), store()));
+                },
+                "Recall"
+            }
+        }
+
+        if !stores.is_empty() {
+            div { class: "d-flex flex-wrap gap-1 mt-1",
+                for setup in stores {
+                    div { class: "btn-group btn-group-sm",
+                        span { class: "input-group-text",
+                            strong { "#{setup.store}" }
+                            span { class: "ms-1 text-muted", {channel_name(setup.channel)} }
+                            span { class: "ms-1", "{setup.label}" }
+                        }
+                        button {
+                            class: "btn btn-sm btn-outline-secondary",
+                            onclick: move |_| {
+                                let selection = match setup.channel {
+                                    None => ChannelSelection::AllChannels,
+                                    Some(ch) => ChannelSelection::Channel(ch),
+                                };
+                                power_supply_action
+                                    .send(PowerSupplyAction::RecallSetup(selection, setup.store));
+                            },
+                            "Recall"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -3,45 +3,71 @@ use std::path::PathBuf;
 use log::warn;
 use serde::{Deserialize, Serialize};
 
+use crate::models::{self, Model};
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct AppConfig {
     path: PathBuf,
     pub data: Config,
 }
 
+/// The configuration file exists but is not valid.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub err: serde_json::Error,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid config {:?}: {}", self.path, self.err)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl AppConfig {
-    pub fn load_from_file(path: PathBuf) -> Self {
+    /// Loads the configuration, a missing or unreadable file gives the default one.
+    pub fn load_from_file(path: PathBuf) -> Result<Self, ConfigError> {
         let data = match std::fs::read_to_string(&path) {
-            Ok(content) => serde_json::from_str(&content).unwrap(),
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(data) => data,
+                Err(err) => return Err(ConfigError { path, err }),
+            },
             Err(err) => {
                 warn!("Failed to load global config {path:?}: {err}");
-                Config {
-                    power_supplies: Vec::new(),
-                }
+                Config::default()
             }
         };
 
-        Self { path, data }
+        Ok(Self { path, data })
     }
 
-    pub fn power_supply(&mut self, id: &str) -> &PowerSupplyConfig {
+    pub fn power_supply(&self, id: &str) -> Option<&PowerSupplyConfig> {
         self.data
             .power_supplies
             .iter()
             .find(|config| config.id == id)
-            .unwrap()
     }
 
-    pub fn power_supply_mut(&mut self, id: &str) -> &mut PowerSupplyConfig {
+    pub fn power_supply_mut(&mut self, id: &str) -> Option<&mut PowerSupplyConfig> {
         self.data
             .power_supplies
             .iter_mut()
             .find(|config| config.id == id)
-            .unwrap()
     }
 
-    pub fn power_supply_channel(&mut self, id: &str, ch: u8) -> &mut ChannelConfig {
-        &mut self.power_supply_mut(id).channels[ch as usize]
+    /// Finds the power supply by its name or id.
+    pub fn find_power_supply(&self, name: &str) -> Option<&PowerSupplyConfig> {
+        let power_supplies = &self.data.power_supplies;
+        power_supplies
+            .iter()
+            .find(|config| config.name == name)
+            .or_else(|| power_supplies.iter().find(|config| config.id == name))
+    }
+
+    pub fn power_supply_channel(&mut self, id: &str, ch: u8) -> Option<&mut ChannelConfig> {
+        self.power_supply_mut(id)?.channels.get_mut(ch as usize)
     }
 
     pub fn save(&mut self) {
@@ -53,12 +79,20 @@ impl AppConfig {
     }
 }
 
+/// Delayed switch-on of the channel when all outputs are switched on.
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct MultiOn {
     pub enabled: bool,
     pub delay_ms: u32,
 }
 
+/// Delayed switch-off of the channel when all outputs are switched off.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct MultiOff {
+    pub enabled: bool,
+    pub delay_ms: u32,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct ChannelConfig {
     pub name: String,
@@ -66,6 +100,8 @@ pub struct ChannelConfig {
     pub current: f32,
     #[serde(default)]
     pub multi_on: MultiOn,
+    #[serde(default)]
+    pub multi_off: MultiOff,
     #[serde(default = "one")]
     pub vrange: u8,
     #[serde(default = "def_true")]
@@ -74,22 +110,301 @@ pub struct ChannelConfig {
     pub overcurrent_trip: Option<f32>,
 }
 
+impl ChannelConfig {
+    /// Default configuration of the channel with zero based index.
+    pub fn new(index: usize) -> Self {
+        Self {
+            name: format!("Channel {}", index + 1),
+            voltage: 0.0,
+            current: 0.0,
+            vrange: 1,
+            auto_vrange: true,
+            overcurrent_trip: None,
+            overvoltage_trip: None,
+            multi_on: MultiOn::default(),
+            multi_off: MultiOff::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SetupStore {
+    /// Channel index, `None` for a store holding all channels.
+    pub channel: Option<u8>,
+    pub store: u8,
+    pub label: String,
+}
+
+/// How often the device is polled.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PollSchedule {
+    /// Pause between polls, pending actions are handled during it.
+    #[serde(default = "default_poll_interval_ms")]
+    pub interval_ms: u64,
+    /// Settings (tracking, ranges, trips, setpoints) are read on every n-th poll,
+    /// other polls read only output state and readbacks.
+    #[serde(default = "default_settings_every")]
+    pub settings_every: u32,
+    /// Number of queries sent before waiting for their replies.
+    #[serde(default = "default_pipeline_depth")]
+    pub pipeline_depth: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Connection {
+    Usb {
+        vid: u16,
+        pid: u16,
+        serial_number: Option<String>,
+    },
+    Tcp {
+        host: String,
+        port: u16,
+    },
+    /// In-process simulated supply reporting the given serial number.
+    Simulator {
+        simulator: String,
+        /// Model name of the simulated supply, MX100QP by default.
+        #[serde(default)]
+        simulated_model: Option<String>,
+    },
+    /// Serial port without USB identification, e.g. a built-in RS-232 port.
+    Serial {
+        serial_port: String,
+    },
+}
+
+impl std::fmt::Display for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Connection::Usb {
+                vid,
+                pid,
+                serial_number,
+            } => {
+                write!(f, "USB {vid:04x}:{pid:04x}")?;
+                if let Some(serial_number) = serial_number {
+                    write!(f, " {serial_number}")?;
+                }
+                Ok(())
+            }
+            Connection::Tcp { host, port } => write!(f, "LAN {host}:{port}"),
+            Connection::Simulator { simulator, .. } => write!(f, "Virtual {simulator}"),
+            Connection::Serial { serial_port } => write!(f, "Serial {serial_port}"),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PowerSupplyConfig {
-    pub vid: u16,
-    pub pid: u16,
-    pub serial_number: Option<String>,
+    #[serde(flatten)]
+    pub connection: Connection,
     pub id: String,
     pub name: String,
+    /// Model detected on the last connection.
+    #[serde(default)]
+    pub model: Option<String>,
     pub channels: Vec<ChannelConfig>,
     #[serde(default = "zero")]
     pub voltage_tracking: u8,
+    #[serde(default = "default_query_timeout_ms")]
+    pub query_timeout_ms: u64,
+    #[serde(default)]
+    pub setup_stores: Vec<SetupStore>,
+    /// File recording every line exchanged with the device.
+    #[serde(default)]
+    pub transcript: Option<PathBuf>,
+    #[serde(default)]
+    pub poll: PollSchedule,
+    /// Address of the SCPI passthrough server of the supply, e.g. `127.0.0.1:9221`.
+    #[serde(default)]
+    pub passthrough: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+impl PowerSupplyConfig {
+    /// Configuration of a supply with default settings and no channels.
+    pub fn new(connection: Connection, id: String, name: String) -> Self {
+        Self {
+            connection,
+            id,
+            name,
+            model: None,
+            channels: Vec::new(),
+            voltage_tracking: zero(),
+            query_timeout_ms: default_query_timeout_ms(),
+            setup_stores: Vec::new(),
+            transcript: None,
+            poll: PollSchedule::default(),
+            passthrough: None,
+        }
+    }
+
+    /// Finds the channel index by its name or number starting from 1.
+    pub fn find_channel(&self, name: &str) -> Option<u8> {
+        if let Some(ch) = self
+            .channels
+            .iter()
+            .position(|channel| channel.name == name)
+        {
+            return Some(ch as u8);
+        }
+
+        match name.parse::<usize>() {
+            Ok(number) if (1..=self.channels.len()).contains(&number) => Some(number as u8 - 1),
+            _ => None,
+        }
+    }
+
+    /// Adapts channels, ranges and tracking to a newly detected model.
+    /// Returns true if the configuration changed.
+    pub fn adapt_to_model(&mut self, model: &Model) -> bool {
+        if self.model.as_deref() == Some(model.name) {
+            return false;
+        }
+
+        // configurations without a model were created for MX100QP
+        let previous = self.model.replace(model.name.to_string());
+        if previous.as_deref().unwrap_or(models::MX100QP.name) != model.name
+            || model.tracking_mode(self.voltage_tracking).is_none()
+        {
+            self.voltage_tracking = model.default_tracking();
+        }
+
+        let channels = model.channels as usize;
+        while self.channels.len() < channels {
+            self.channels.push(ChannelConfig::new(self.channels.len()));
+        }
+        self.channels.truncate(channels);
+
+        for (ch, channel) in self.channels.iter_mut().enumerate() {
+            let ranges: Vec<u8> = model
+                .selectable_ranges(ch as u8)
+                .map(|(value, _)| value)
+                .collect();
+            if !ranges.contains(&channel.vrange)
+                && let Some(value) = ranges.first()
+            {
+                channel.vrange = *value;
+            }
+        }
+
+        true
+    }
+}
+
+/// Embedded HTTP API of the GUI.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ApiConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_api_bind")]
+    pub bind: String,
+    /// Token required by endpoints changing the supplies, they are disabled without it.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Serves Prometheus metrics on `/metrics`.
+    #[serde(default)]
+    pub metrics: bool,
+}
+
+/// MQTT client of the GUI publishing readings and receiving commands.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct MqttConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_mqtt_host")]
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// First level of all topics.
+    #[serde(default = "default_mqtt_prefix")]
+    pub prefix: String,
+    /// Minimal interval between readings of a supply.
+    #[serde(default = "default_mqtt_interval_ms")]
+    pub interval_ms: u64,
+}
+
+/// File format of logged readings.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+impl LogFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Logging of readings into files in the `logs` directory of the configuration.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DataLogConfig {
+    /// Format preselected when starting a log.
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Rows per file, a new file is started after them and on a new day.
+    #[serde(default = "default_log_rows")]
+    pub max_rows: u64,
+}
+
+/// Step of a power sequence, supplies are addressed by name or id, channels by name or number starting from 1.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum SequenceStep {
+    On {
+        supply: String,
+        channel: String,
+    },
+    Off {
+        supply: String,
+        channel: String,
+    },
+    /// Waits until the channel is on and its voltage readback is within `tolerance` percent of the setpoint.
+    WaitVoltage {
+        supply: String,
+        channel: String,
+        #[serde(default = "default_sequence_tolerance")]
+        tolerance: f32,
+        #[serde(default = "default_sequence_timeout_ms")]
+        timeout_ms: u64,
+    },
+    Delay {
+        ms: u64,
+    },
+}
+
+/// Named sequence of steps run by the software sequencer of the GUI.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SequenceConfig {
+    pub name: String,
+    pub steps: Vec<SequenceStep>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Config {
     #[serde(default)]
     pub power_supplies: Vec<PowerSupplyConfig>,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub datalog: DataLogConfig,
+    #[serde(default)]
+    pub sequences: Vec<SequenceConfig>,
 }
 
 fn one() -> u8 {
@@ -100,10 +415,107 @@ fn zero() -> u8 {
     0
 }
 
+fn default_query_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_poll_interval_ms() -> u64 {
+    100
+}
+
+fn default_settings_every() -> u32 {
+    10
+}
+
+fn default_pipeline_depth() -> usize {
+    8
+}
+
+fn default_api_bind() -> String {
+    "127.0.0.1:8080".to_string()
+}
+
+fn default_mqtt_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "power-supply-control".to_string()
+}
+
+fn default_mqtt_prefix() -> String {
+    "power-supply".to_string()
+}
+
+fn default_mqtt_interval_ms() -> u64 {
+    1000
+}
+
+fn default_log_rows() -> u64 {
+    1_000_000
+}
+
+fn default_sequence_tolerance() -> f32 {
+    5.0
+}
+
+fn default_sequence_timeout_ms() -> u64 {
+    5000
+}
+
 fn def_true() -> bool {
     true
 }
 
+impl Default for PollSchedule {
+    fn default() -> Self {
+        Self {
+            interval_ms: default_poll_interval_ms(),
+            settings_every: default_settings_every(),
+            pipeline_depth: default_pipeline_depth(),
+        }
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_api_bind(),
+            token: None,
+            metrics: false,
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: default_mqtt_host(),
+            port: default_mqtt_port(),
+            client_id: default_mqtt_client_id(),
+            username: None,
+            password: None,
+            prefix: default_mqtt_prefix(),
+            interval_ms: default_mqtt_interval_ms(),
+        }
+    }
+}
+
+impl Default for DataLogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            max_rows: default_log_rows(),
+        }
+    }
+}
+
 impl Default for MultiOn {
     fn default() -> Self {
         Self {
@@ -112,3 +524,157 @@ impl Default for MultiOn {
         }
     }
 }
+
+impl Default for MultiOff {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay_ms: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_from_file() {
+        let path = std::env::temp_dir().join(format!("config-{}.json", std::process::id()));
+        let config = AppConfig::load_from_file(path.clone()).unwrap();
+        assert!(config.data.power_supplies.is_empty());
+
+        std::fs::write(&path, "{").unwrap();
+        let err = AppConfig::load_from_file(path.clone()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.path, path);
+    }
+
+    #[test]
+    fn test_connection() {
+        let usb: PowerSupplyConfig = serde_json::from_str(
+            r#"{"vid": 1027, "pid": 24577, "serial_number": "ABC", "id": "123", "name": "usb", "channels": []}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            usb.connection,
+            Connection::Usb {
+                vid: 1027,
+                pid: 24577,
+                serial_number: Some("ABC".to_string())
+            }
+        );
+
+        let tcp: PowerSupplyConfig = serde_json::from_str(
+            r#"{"host": "10.0.0.5", "port": 9221, "id": "123", "name": "lan", "channels": []}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            tcp.connection,
+            Connection::Tcp {
+                host: "10.0.0.5".to_string(),
+                port: 9221
+            }
+        );
+
+        let json = serde_json::to_value(&tcp).unwrap();
+        assert_eq!(json["host"], "10.0.0.5");
+        assert_eq!(json["port"], 9221);
+
+        let serial: PowerSupplyConfig = serde_json::from_str(
+            r#"{"serial_port": "/dev/ttyS0", "id": "123", "name": "rs232", "channels": []}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serial.connection,
+            Connection::Serial {
+                serial_port: "/dev/ttyS0".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_adapt_to_model() {
+        let mut config: PowerSupplyConfig = serde_json::from_str(
+            r#"{"host": "10.0.0.5", "port": 9221, "id": "123", "name": "lan", "channels": []}"#,
+        )
+        .unwrap();
+        config.channels = (0..4).map(ChannelConfig::new).collect();
+        config.voltage_tracking = 3;
+
+        let mut legacy = config.clone();
+        assert!(legacy.adapt_to_model(&models::MX100QP));
+        assert_eq!(legacy.voltage_tracking, 3);
+
+        assert!(config.adapt_to_model(&models::QL355TP));
+        assert_eq!(config.model.as_deref(), Some("QL355TP"));
+        assert_eq!(config.channels.len(), 3);
+        assert_eq!(config.channels[0].vrange, 1);
+        assert_eq!(config.channels[2].vrange, 0);
+        assert_eq!(config.voltage_tracking, 2);
+
+        config.channels[0].name = "core".to_string();
+        assert!(!config.adapt_to_model(&models::QL355TP));
+        assert!(config.adapt_to_model(&models::MX100QP));
+        assert_eq!(config.channels.len(), 4);
+        assert_eq!(config.channels[0].name, "core");
+        assert_eq!(config.channels[3].name, "Channel 4");
+        assert_eq!(config.voltage_tracking, 0);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut config: PowerSupplyConfig = serde_json::from_str(
+            r#"{"host": "10.0.0.5", "port": 9221, "id": "123", "name": "bench", "channels": []}"#,
+        )
+        .unwrap();
+        config.channels = (0..4).map(ChannelConfig::new).collect();
+        config.channels[1].name = "3".to_string();
+        let mut appconfig = AppConfig {
+            path: PathBuf::new(),
+            data: Config {
+                power_supplies: vec![config],
+                ..Default::default()
+            },
+        };
+
+        assert!(appconfig.power_supply("bench").is_none());
+        assert!(appconfig.power_supply_channel("123", 3).is_some());
+        assert!(appconfig.power_supply_channel("123", 4).is_none());
+        assert!(appconfig.power_supply_channel("456", 0).is_none());
+
+        assert_eq!(appconfig.find_power_supply("bench").unwrap().id, "123");
+        assert_eq!(appconfig.find_power_supply("123").unwrap().name, "bench");
+        assert!(appconfig.find_power_supply("other").is_none());
+
+        let config = appconfig.find_power_supply("bench").unwrap();
+        assert_eq!(config.find_channel("Channel 1"), Some(0));
+        assert_eq!(config.find_channel("3"), Some(1));
+        assert_eq!(config.find_channel("4"), Some(3));
+        assert_eq!(config.find_channel("5"), None);
+        assert_eq!(config.find_channel("0"), None);
+    }
+
+    #[test]
+    fn test_sequences() {
+        let config: Config = serde_json::from_str(
+            r#"{"sequences": [{"name": "up", "steps": [
+                {"step": "on", "supply": "bench", "channel": "core"},
+                {"step": "wait_voltage", "supply": "bench", "channel": "core"},
+                {"step": "delay", "ms": 200}
+            ]}]}"#,
+        )
+        .unwrap();
+        let steps = &config.sequences[0].steps;
+        assert_eq!(
+            steps[1],
+            SequenceStep::WaitVoltage {
+                supply: "bench".to_string(),
+                channel: "core".to_string(),
+                tolerance: 5.0,
+                timeout_ms: 5000,
+            }
+        );
+        assert_eq!(steps[2], SequenceStep::Delay { ms: 200 });
+    }
+}
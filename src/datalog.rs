@@ -0,0 +1,578 @@
+//! Logging of polled readings into CSV or Parquet files, rotated daily and after a number of rows.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use power_supply_control::config::{DataLogConfig, LogFormat};
+
+use crate::supplies::{ChannelState, Event, Frame};
+
+#[derive(Debug)]
+pub enum DataLogError {
+    Io(std::io::Error),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    /// Parquet was requested from a build without it.
+    Unsupported(LogFormat),
+}
+
+impl std::fmt::Display for DataLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataLogError::Io(err) => write!(f, "{err}"),
+            #[cfg(feature = "parquet")]
+            DataLogError::Parquet(err) => write!(f, "{err}"),
+            DataLogError::Unsupported(format) => {
+                write!(
+                    f,
+                    "Logging to {} is not supported by this build",
+                    format.extension()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DataLogError {}
+
+impl From<std::io::Error> for DataLogError {
+    fn from(err: std::io::Error) -> Self {
+        DataLogError::Io(err)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for DataLogError {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        DataLogError::Parquet(err)
+    }
+}
+
+/// One line of the log, a channel of a poll, or a gap in the readings without a channel.
+struct Row<'a> {
+    timestamp: DateTime<Local>,
+    supply: &'a str,
+    name: &'a str,
+    channel: Option<&'a ChannelState>,
+}
+
+const CSV_HEADER: &str = "timestamp,supply,name,channel,channel_name,enabled,voltage,voltage_set,current,current_set,status,voltage_min,voltage_max,voltage_mean,voltage_rms,current_min,current_max,current_mean,current_rms,gap";
+
+/// Names of the statistics columns, in the order of [`stats_values`].
+#[cfg(feature = "parquet")]
+const STATS_COLUMNS: [&str; 8] = [
+    "voltage_min",
+    "voltage_max",
+    "voltage_mean",
+    "voltage_rms",
+    "current_min",
+    "current_max",
+    "current_mean",
+    "current_rms",
+];
+
+/// Statistics of the channel since their last reset, empty before the first sample.
+fn stats_values(channel: &ChannelState) -> [Option<f32>; 8] {
+    let Some(stats) = &channel.stats else {
+        return [None; 8];
+    };
+    let (v, c) = (stats.voltage, stats.current);
+    [v.min, v.max, v.mean, v.rms, c.min, c.max, c.mean, c.rms].map(Some)
+}
+
+/// Quotes the field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_csv(out: &mut impl Write, row: &Row) -> std::io::Result<()> {
+    write!(
+        out,
+        "{},{},{}",
+        row.timestamp
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+        csv_field(row.supply),
+        csv_field(row.name),
+    )?;
+    let Some(ch) = row.channel else {
+        // all columns of the channel are empty, only the gap is set
+        let empty = CSV_HEADER.matches(',').count() - 3;
+        return writeln!(out, "{},1", ",".repeat(empty));
+    };
+
+    write!(
+        out,
+        ",{},{},{},{},{},{},{},{}",
+        ch.channel,
+        csv_field(&ch.name),
+        u8::from(ch.enabled),
+        ch.voltage,
+        ch.voltage_set,
+        ch.current,
+        ch.current_set,
+        ch.status.join("|"),
+    )?;
+    for value in stats_values(ch) {
+        match value {
+            Some(value) => write!(out, ",{value}")?,
+            None => write!(out, ",")?,
+        }
+    }
+    writeln!(out, ",0")
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_file {
+    use std::{fs::File, sync::Arc};
+
+    use arrow_array::{
+        ArrayRef, BooleanArray, Float32Array, RecordBatch, StringArray, TimestampMillisecondArray,
+        UInt8Array,
+    };
+    use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+    use parquet::{arrow::ArrowWriter, errors::ParquetError, file::properties::WriterProperties};
+
+    use super::{ChannelState, Row, STATS_COLUMNS, stats_values};
+
+    /// Rows kept in memory before they are written as a row group.
+    const ROW_GROUP_SIZE: usize = 4096;
+
+    fn schema() -> SchemaRef {
+        let timestamp = DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()));
+        let fields = vec![
+            Field::new("timestamp", timestamp, false),
+            Field::new("supply", DataType::Utf8, false),
+            Field::new("name", DataType::Utf8, false),
+            // columns of the channel are null in gap rows
+            Field::new("channel", DataType::UInt8, true),
+            Field::new("channel_name", DataType::Utf8, true),
+            Field::new("enabled", DataType::Boolean, true),
+            Field::new("voltage", DataType::Float32, true),
+            Field::new("voltage_set", DataType::Float32, true),
+            Field::new("current", DataType::Float32, true),
+            Field::new("current_set", DataType::Float32, true),
+            Field::new("status", DataType::Utf8, true),
+        ];
+        let stats = STATS_COLUMNS.map(|name| Field::new(name, DataType::Float32, true));
+        let gap = Field::new("gap", DataType::Boolean, false);
+        Arc::new(Schema::new([fields, stats.to_vec(), vec![gap]].concat()))
+    }
+
+    pub fn create(file: File) -> Result<ArrowWriter<File>, ParquetError> {
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+        ArrowWriter::try_new(file, schema(), Some(properties))
+    }
+
+    pub fn write(writer: &mut ArrowWriter<File>, rows: &[Row]) -> Result<(), ParquetError> {
+        let strings = |field: fn(&ChannelState) -> String| -> ArrayRef {
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|row| row.channel.map(field)),
+            ))
+        };
+        let floats = |field: fn(&ChannelState) -> f32| -> ArrayRef {
+            Arc::new(Float32Array::from_iter(
+                rows.iter().map(|row| row.channel.map(field)),
+            ))
+        };
+        let timestamps = TimestampMillisecondArray::from_iter_values(
+            rows.iter().map(|row| row.timestamp.timestamp_millis()),
+        )
+        .with_timezone("UTC");
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(timestamps),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|row| row.supply),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|row| row.name),
+            )),
+            Arc::new(UInt8Array::from_iter(
+                rows.iter().map(|row| row.channel.map(|ch| ch.channel)),
+            )),
+            strings(|ch| ch.name.clone()),
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|row| row.channel.map(|ch| ch.enabled)),
+            )),
+            floats(|ch| ch.voltage),
+            floats(|ch| ch.voltage_set),
+            floats(|ch| ch.current),
+            floats(|ch| ch.current_set),
+            strings(|ch| ch.status.join("|")),
+        ];
+        let stats: Vec<[Option<f32>; 8]> = rows
+            .iter()
+            .map(|row| row.channel.map_or([None; 8], stats_values))
+            .collect();
+        for column in 0..STATS_COLUMNS.len() {
+            columns.push(Arc::new(Float32Array::from_iter(
+                stats.iter().map(|values| values[column]),
+            )));
+        }
+        columns.push(Arc::new(BooleanArray::from_iter(
+            rows.iter().map(|row| Some(row.channel.is_none())),
+        )));
+        let batch = RecordBatch::try_new(schema(), columns)?;
+        writer.write(&batch)
+    }
+}
+
+enum Writer {
+    Csv(BufWriter<File>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<parquet::arrow::ArrowWriter<File>>),
+}
+
+impl Writer {
+    fn write(&mut self, rows: &[Row]) -> Result<(), DataLogError> {
+        match self {
+            Writer::Csv(out) => {
+                for row in rows {
+                    write_csv(out, row)?;
+                }
+                // keep the file usable while the log is running
+                out.flush()?;
+            }
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(writer) => parquet_file::write(writer, rows)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), DataLogError> {
+        match self {
+            Writer::Csv(mut out) => out.flush()?,
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(writer) => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replaces characters not suitable for file names.
+fn file_name_part(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Logs readings of a supply into `<date>_<session>_<supply>[_<part>].<format>` files.
+pub struct DataLogger {
+    dir: PathBuf,
+    /// File name without the date and extension.
+    name: String,
+    format: LogFormat,
+    max_rows: u64,
+    date: NaiveDate,
+    path: PathBuf,
+    rows: u64,
+    writer: Option<Writer>,
+}
+
+impl DataLogger {
+    /// Creates the first file of the session in the directory.
+    pub fn create(
+        dir: &Path,
+        supply: &str,
+        session: &str,
+        format: LogFormat,
+        config: &DataLogConfig,
+    ) -> Result<Self, DataLogError> {
+        if cfg!(not(feature = "parquet")) && format == LogFormat::Parquet {
+            return Err(DataLogError::Unsupported(format));
+        }
+        std::fs::create_dir_all(dir)?;
+
+        let name = match file_name_part(session) {
+            session if session.is_empty() => file_name_part(supply),
+            session => format!("{session}_{}", file_name_part(supply)),
+        };
+        let mut logger = Self {
+            dir: dir.to_path_buf(),
+            name,
+            format,
+            max_rows: config.max_rows.max(1),
+            date: Local::now().date_naive(),
+            path: PathBuf::new(),
+            rows: 0,
+            writer: None,
+        };
+        logger.open()?;
+        Ok(logger)
+    }
+
+    /// File currently written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Starts a new file, never overwriting files of previous sessions.
+    fn open(&mut self) -> Result<(), DataLogError> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+
+        let base = format!("{}_{}", self.date.format("%Y-%m-%d"), self.name);
+        let extension = self.format.extension();
+        let (path, file) = (1..)
+            .map(|part| match part {
+                1 => self.dir.join(format!("{base}.{extension}")),
+                part => self.dir.join(format!("{base}_{part}.{extension}")),
+            })
+            .find_map(|path| {
+                let file = File::create_new(&path);
+                match file {
+                    Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => None,
+                    file => Some((path, file)),
+                }
+            })
+            .unwrap();
+        let file = file?;
+
+        let writer = match self.format {
+            LogFormat::Csv => {
+                let mut out = BufWriter::new(file);
+                writeln!(out, "{CSV_HEADER}")?;
+                Writer::Csv(out)
+            }
+            #[cfg(feature = "parquet")]
+            LogFormat::Parquet => Writer::Parquet(Box::new(parquet_file::create(file)?)),
+            #[cfg(not(feature = "parquet"))]
+            LogFormat::Parquet => return Err(DataLogError::Unsupported(self.format)),
+        };
+        self.path = path;
+        self.rows = 0;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    /// Writes readings of the frame, other events are ignored.
+    pub fn log(&mut self, frame: &Frame) -> Result<(), DataLogError> {
+        let Event::Readings { channels, .. } = &frame.event else {
+            return Ok(());
+        };
+        let timestamp = DateTime::from_timestamp_millis((frame.timestamp * 1000.0) as i64)
+            .unwrap_or_default()
+            .with_timezone(&Local);
+        let channels: Vec<_> = channels.iter().map(Some).collect();
+        self.write(timestamp, &frame.supply, &frame.name, &channels)
+    }
+
+    /// Writes a row marking readings missing from the log, with `gap` set and the columns
+    /// of the channel empty.
+    pub fn gap(&mut self, supply: &str, name: &str) -> Result<(), DataLogError> {
+        self.write(Local::now(), supply, name, &[None])
+    }
+
+    fn write(
+        &mut self,
+        timestamp: DateTime<Local>,
+        supply: &str,
+        name: &str,
+        channels: &[Option<&ChannelState>],
+    ) -> Result<(), DataLogError> {
+        if timestamp.date_naive() != self.date || self.rows >= self.max_rows {
+            self.date = timestamp.date_naive();
+            self.open()?;
+        }
+
+        let rows: Vec<Row> = channels
+            .iter()
+            .map(|channel| Row {
+                timestamp,
+                supply,
+                name,
+                channel: *channel,
+            })
+            .collect();
+        if let Some(writer) = &mut self.writer {
+            writer.write(&rows)?;
+        }
+        self.rows += rows.len() as u64;
+        Ok(())
+    }
+}
+
+impl Drop for DataLogger {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take()
+            && let Err(err) = writer.finish()
+        {
+            log::error!("Failed to finish {:?}: {err}", self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        stats::{StatsSummary, Summary},
+        test_support,
+    };
+
+    use super::*;
+
+    fn frame(timestamp: f64) -> Frame {
+        let mut channels = test_support::supply(&["core, 1V8", "core, 1V8"]).channels;
+        channels[0] = ChannelState {
+            enabled: true,
+            voltage: 1.75,
+            voltage_set: 1.8,
+            current: 0.5,
+            current_set: 1.0,
+            status: vec!["CURRENT_LIMIT", "OVER_CURRENT_TRIP"],
+            stats: Some(StatsSummary {
+                samples: 3,
+                voltage: Summary {
+                    min: 1.7,
+                    max: 1.8,
+                    mean: 1.75,
+                    rms: 1.75,
+                },
+                current: Summary {
+                    min: 0.1,
+                    max: 2.0,
+                    mean: 0.5,
+                    rms: 0.75,
+                },
+            }),
+            ..channels[0].clone()
+        };
+        channels[1] = ChannelState {
+            channel: 2,
+            stats: None,
+            ..channels[0].clone()
+        };
+        Frame {
+            timestamp,
+            supply: "SIM".to_string(),
+            name: "bench".to_string(),
+            event: Event::Readings {
+                channels,
+                started: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let dir = std::env::temp_dir().join(format!("datalog-{}", std::process::id()));
+        let config = DataLogConfig {
+            format: LogFormat::Csv,
+            max_rows: 4,
+        };
+        let now = Local::now().timestamp_millis() as f64 / 1000.0;
+
+        let mut logger =
+            DataLogger::create(&dir, "bench", "boot test", LogFormat::Csv, &config).unwrap();
+        let first = logger.path().to_path_buf();
+        let date = Local::now().format("%Y-%m-%d");
+        assert_eq!(first, dir.join(format!("{date}_boot_test_bench.csv")));
+
+        logger.log(&frame(now)).unwrap();
+        logger.log(&frame(now)).unwrap();
+        // rotated after max_rows
+        logger.log(&frame(now)).unwrap();
+        assert_eq!(
+            logger.path(),
+            dir.join(format!("{date}_boot_test_bench_2.csv"))
+        );
+        logger.gap("SIM", "bench").unwrap();
+        let second = logger.path().to_path_buf();
+        drop(logger);
+
+        let content = std::fs::read_to_string(&second).unwrap();
+        assert!(
+            content
+                .lines()
+                .last()
+                .unwrap()
+                .ends_with(",SIM,bench,,,,,,,,,,,,,,,,,1")
+        );
+
+        let content = std::fs::read_to_string(&first).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].ends_with(
+            ",SIM,bench,1,\"core, 1V8\",1,1.75,1.8,0.5,1,CURRENT_LIMIT|OVER_CURRENT_TRIP,1.7,1.8,1.75,1.75,0.1,2,0.5,0.75,0"
+        ));
+        // statistics are empty before the first sample
+        assert!(lines[2].ends_with(",CURRENT_LIMIT|OVER_CURRENT_TRIP,,,,,,,,,0"));
+
+        // a new session of the same name does not overwrite the files
+        let logger =
+            DataLogger::create(&dir, "bench", "boot test", LogFormat::Csv, &config).unwrap();
+        assert_eq!(
+            logger.path(),
+            dir.join(format!("{date}_boot_test_bench_3.csv"))
+        );
+        drop(logger);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet() {
+        use arrow_array::{Array, BooleanArray, Float32Array, StringArray};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let dir = std::env::temp_dir().join(format!("datalog-parquet-{}", std::process::id()));
+        let config = DataLogConfig::default();
+        let now = Local::now().timestamp_millis() as f64 / 1000.0;
+
+        let mut logger =
+            DataLogger::create(&dir, "bench", "", LogFormat::Parquet, &config).unwrap();
+        let path = logger.path().to_path_buf();
+        logger.log(&frame(now)).unwrap();
+        logger.log(&frame(now)).unwrap();
+        logger.gap("SIM", "bench").unwrap();
+        drop(logger);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 5);
+
+        let batch = &batches[0];
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let names = column("channel_name");
+        let names = names.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(names.value(0), "core, 1V8");
+        let voltage = column("voltage");
+        let voltage = voltage.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_eq!(voltage.value(0), 1.75);
+        let peak = column("current_max");
+        let peak = peak.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_eq!(peak.value(0), 2.0);
+        assert!(peak.is_null(1));
+        let gap = column("gap");
+        let gap = gap.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert!(!gap.value(0));
+        assert!(gap.value(4));
+        assert!(voltage.is_null(4));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use futures::future::join_all;
+use log::{debug, info, warn};
+use serialport::{SerialPortInfo, SerialPortType};
+
+use crate::{
+    config::Connection,
+    line_codec::LineCodec,
+    models::{self, Model},
+    mx100qp::{Identity, OpenError, handshake},
+    transport::{self, Transport},
+};
+
+/// Deadline for a candidate port to answer `*IDN?`.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// USB vendor ids of TTi and of the USB-serial bridges used by its supplies and RS-232 adapters.
+const USB_VENDORS: &[u16] = &[
+    0x103e, // Thurlby Thandar Instruments
+    0x0403, // FTDI
+    0x067b, // Prolific
+    0x10c4, // Silicon Labs CP210x
+    0x1a86, // WCH CH340
+];
+
+/// Supported power supply found by probing a serial port.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredSupply {
+    pub port_name: String,
+    pub connection: Connection,
+    pub identity: Identity,
+    pub model: &'static Model,
+}
+
+impl std::fmt::Display for DiscoveredSupply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}, {}",
+            self.identity.manufacturer, self.identity.model, self.identity.serial_number
+        )?;
+        if !self.identity.firmware.is_empty() {
+            write!(f, ", fw {}", self.identity.firmware)?;
+        }
+        write!(f, " ({})", self.port_name)
+    }
+}
+
+/// Connection describing the port, USB ports are matched by VID/PID and serial number if any.
+fn connection_for(port: &SerialPortInfo) -> Connection {
+    match &port.port_type {
+        SerialPortType::UsbPort(usbinfo) => Connection::Usb {
+            vid: usbinfo.vid,
+            pid: usbinfo.pid,
+            serial_number: usbinfo.serial_number.clone(),
+        },
+        _ => Connection::Serial {
+            serial_port: port.port_name.clone(),
+        },
+    }
+}
+
+/// Returns true for ports probed by default, USB ports of TTi devices and USB-serial bridges.
+fn is_candidate(port: &SerialPortInfo) -> bool {
+    matches!(
+        &port.port_type,
+        SerialPortType::UsbPort(usbinfo) if USB_VENDORS.contains(&usbinfo.vid)
+    )
+}
+
+/// Asks the device for its identity, failing if it does not answer in time.
+pub async fn probe(transport: Box<dyn Transport>) -> Result<Identity, OpenError> {
+    let (_, identity) = handshake(transport, LineCodec::default(), PROBE_TIMEOUT).await?;
+    Ok(identity)
+}
+
+async fn probe_port(port: SerialPortInfo) -> Option<DiscoveredSupply> {
+    let identity = match transport::open_serial(&port.port_name) {
+        Ok(transport) => probe(transport).await,
+        Err(err) => Err(err),
+    };
+
+    let identity = match identity {
+        Ok(identity) => identity,
+        Err(err) => {
+            debug!("No supply on {}: {err}", port.port_name);
+            return None;
+        }
+    };
+
+    let Some(model) = models::find(&identity.model) else {
+        info!(
+            "Ignoring unsupported {} on {}",
+            identity.model, port.port_name
+        );
+        return None;
+    };
+
+    Some(DiscoveredSupply {
+        connection: connection_for(&port),
+        port_name: port.port_name,
+        identity,
+        model,
+    })
+}
+
+/// Probes serial ports with `*IDN?` in parallel and returns the supported supplies.
+///
+/// Other ports than those of TTi devices and USB-serial bridges are probed only with
+/// `all_ports`, as writing to them may disturb unrelated devices. Ports already opened
+/// by connected supplies cannot be opened again and are skipped.
+pub async fn discover(all_ports: bool) -> Vec<DiscoveredSupply> {
+    let ports = match serialport::available_ports() {
+        Ok(ports) => ports,
+        Err(err) => {
+            warn!("Failed to list serial ports: {err}");
+            return Vec::new();
+        }
+    };
+
+    let ports = ports.into_iter().filter(|port| {
+        let probed = all_ports || is_candidate(port);
+        if !probed {
+            debug!("Not probing {}", port.port_name);
+        }
+        probed
+    });
+    join_all(ports.map(probe_port))
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::MX100QP, simulator::Simulator};
+    use serialport::UsbPortInfo;
+
+    #[tokio::test]
+    async fn test_probe() {
+        let identity = probe(Box::new(Simulator::new(&MX100QP, "SIM")))
+            .await
+            .unwrap();
+        assert_eq!(identity.manufacturer, "THURLBY THANDAR");
+        assert_eq!(identity.model, "MX100QP");
+        assert_eq!(identity.serial_number, "SIM");
+        assert_eq!(identity.firmware, "1.00-1.00-1.00");
+    }
+
+    #[test]
+    fn test_connection_for() {
+        let adapter = SerialPortInfo {
+            port_name: "/dev/ttyUSB0".to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: 0x067b,
+                pid: 0x2303,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+            }),
+        };
+        assert!(is_candidate(&adapter));
+        assert_eq!(
+            connection_for(&adapter),
+            Connection::Usb {
+                vid: 0x067b,
+                pid: 0x2303,
+                serial_number: None
+            }
+        );
+
+        let builtin = SerialPortInfo {
+            port_name: "/dev/ttyS0".to_string(),
+            port_type: SerialPortType::Unknown,
+        };
+        assert!(!is_candidate(&builtin));
+        assert_eq!(
+            connection_for(&builtin),
+            Connection::Serial {
+                serial_port: "/dev/ttyS0".to_string()
+            }
+        );
+
+        let modem = SerialPortInfo {
+            port_name: "/dev/ttyACM0".to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: 0x1199,
+                pid: 0x9071,
+                serial_number: Some("MODEM".to_string()),
+                manufacturer: None,
+                product: None,
+            }),
+        };
+        assert!(!is_candidate(&modem));
+    }
+}
@@ -0,0 +1,137 @@
+//! Power, energy and charge of the channels integrated over their polls.
+
+use std::time::{Duration, Instant};
+
+use power_supply_control::mx100qp::Channel;
+
+use crate::history::Recorder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Reading {
+    time: Instant,
+    power: f64,
+    current: f64,
+}
+
+/// Energy and charge delivered by a channel since the last reset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Energy {
+    pub running: bool,
+    /// Power of the last poll in W.
+    pub power: f64,
+    pub watt_hours: f64,
+    pub amp_hours: f64,
+    /// Time the totals were integrated over.
+    pub elapsed: Duration,
+    last: Option<Reading>,
+}
+
+impl Default for Energy {
+    fn default() -> Self {
+        Self {
+            running: true,
+            power: 0.0,
+            watt_hours: 0.0,
+            amp_hours: 0.0,
+            elapsed: Duration::ZERO,
+            last: None,
+        }
+    }
+}
+
+impl Recorder for Energy {
+    /// Integrates the readbacks of a poll with the trapezoidal rule.
+    fn record(&mut self, time: Instant, channel: &Channel) {
+        let (voltage, current) = match channel.enabled {
+            true => (
+                channel.voltage.current as f64,
+                channel.current.current as f64,
+            ),
+            false => (0.0, 0.0),
+        };
+        let reading = Reading {
+            time,
+            power: voltage * current,
+            current,
+        };
+        self.power = reading.power;
+
+        if self.running
+            && let Some(last) = self.last
+        {
+            let dt = time.saturating_duration_since(last.time);
+            let hours = dt.as_secs_f64() / 3600.0;
+            self.watt_hours += (last.power + reading.power) / 2.0 * hours;
+            self.amp_hours += (last.current + reading.current) / 2.0 * hours;
+            self.elapsed += dt;
+        }
+        self.last = self.running.then_some(reading);
+    }
+}
+
+impl Energy {
+    /// Stops integrating across a gap in readings, e.g. while the supply is disconnected.
+    pub fn interrupt(&mut self) {
+        self.last = None;
+    }
+
+    pub fn start(&mut self) {
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.last = None;
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            running: self.running,
+            power: self.power,
+            last: self.last,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::channel;
+
+    #[test]
+    fn test_energy() {
+        let start = Instant::now();
+        let hour = Duration::from_secs(3600);
+        let mut energy = Energy::default();
+
+        energy.record(start, &channel(true, 5.0, 1.0));
+        energy.record(start + hour, &channel(true, 5.0, 3.0));
+        assert_eq!(energy.power, 15.0);
+        assert_eq!(energy.watt_hours, 10.0);
+        assert_eq!(energy.amp_hours, 2.0);
+
+        // the gap while disconnected is not integrated
+        energy.interrupt();
+        energy.record(start + hour * 5, &channel(true, 5.0, 3.0));
+        assert_eq!(energy.watt_hours, 10.0);
+        assert_eq!(energy.elapsed, hour);
+
+        // nothing is delivered by a disabled output
+        energy.record(start + hour * 6, &channel(false, 5.0, 3.0));
+        assert_eq!(energy.watt_hours, 17.5);
+
+        energy.stop();
+        energy.record(start + hour * 7, &channel(true, 5.0, 1.0));
+        energy.start();
+        energy.record(start + hour * 8, &channel(true, 5.0, 1.0));
+        assert_eq!(energy.watt_hours, 17.5);
+        energy.record(start + hour * 9, &channel(true, 5.0, 1.0));
+        assert_eq!(energy.watt_hours, 22.5);
+
+        energy.reset();
+        assert!(energy.running);
+        assert_eq!(energy.watt_hours, 0.0);
+        assert_eq!(energy.elapsed, Duration::ZERO);
+    }
+}
@@ -0,0 +1,348 @@
+//! History of channel readbacks with on/off and trip markers, scaled into SVG charts.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use power_supply_control::mx100qp::{Channel, LimitEventStatus};
+
+/// Samples older than this are dropped, it is the longest selectable window.
+pub const RETENTION: Duration = Duration::from_secs(3600);
+
+/// Selectable time windows of the charts.
+pub const WINDOWS: [(Duration, &str); 4] = [
+    (Duration::from_secs(60), "1 min"),
+    (Duration::from_secs(300), "5 min"),
+    (Duration::from_secs(900), "15 min"),
+    (RETENTION, "1 h"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    time: Instant,
+    voltage: f32,
+    current: f32,
+    voltage_set: f32,
+    current_set: f32,
+    overvoltage_trip: Option<f32>,
+    overcurrent_trip: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarkerKind {
+    On,
+    Off,
+    Trip,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Marker {
+    time: Instant,
+    kind: MarkerKind,
+    label: String,
+}
+
+/// Per-channel state updated with the readings of every poll.
+pub trait Recorder: Default {
+    fn record(&mut self, time: Instant, channel: &Channel);
+}
+
+/// Records readings of all channels of a supply, one recorder per channel.
+pub fn record_all<T: Recorder>(recorders: &mut Vec<T>, time: Instant, channels: &[Channel]) {
+    recorders.resize_with(channels.len(), T::default);
+    for (recorder, channel) in recorders.iter_mut().zip(channels) {
+        recorder.record(time, channel);
+    }
+}
+
+/// Readbacks of a channel over the last [`RETENTION`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelHistory {
+    samples: VecDeque<Sample>,
+    markers: VecDeque<Marker>,
+    enabled: Option<bool>,
+    status: LimitEventStatus,
+}
+
+impl Recorder for ChannelHistory {
+    /// Adds readings of a poll, marking changes of the output and new trips.
+    fn record(&mut self, time: Instant, channel: &Channel) {
+        if let Some(enabled) = self.enabled
+            && enabled != channel.enabled
+        {
+            let (kind, label) = match channel.enabled {
+                true => (MarkerKind::On, "ON"),
+                false => (MarkerKind::Off, "OFF"),
+            };
+            self.markers.push_back(Marker {
+                time,
+                kind,
+                label: label.to_string(),
+            });
+        }
+        self.enabled = Some(channel.enabled);
+
+        let trips = channel.status & LimitEventStatus::TRIPS;
+        for (trip, _) in trips.difference(self.status).iter_names() {
+            self.markers.push_back(Marker {
+                time,
+                kind: MarkerKind::Trip,
+                label: trip.to_string(),
+            });
+        }
+        self.status = channel.status;
+
+        self.samples.push_back(Sample {
+            time,
+            voltage: channel.voltage.current,
+            current: channel.current.current,
+            voltage_set: channel.voltage.set,
+            current_set: channel.current.set,
+            overvoltage_trip: channel.overvoltage_trip,
+            overcurrent_trip: channel.overcurrent_trip,
+        });
+
+        let expired = |at: Instant| time.saturating_duration_since(at) > RETENTION;
+        while self
+            .samples
+            .front()
+            .is_some_and(|sample| expired(sample.time))
+        {
+            self.samples.pop_front();
+        }
+        while self
+            .markers
+            .front()
+            .is_some_and(|marker| expired(marker.time))
+        {
+            self.markers.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantity {
+    Voltage,
+    Current,
+}
+
+impl Quantity {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Quantity::Voltage => "V",
+            Quantity::Current => "A",
+        }
+    }
+
+    fn value(&self, sample: &Sample) -> f32 {
+        match self {
+            Quantity::Voltage => sample.voltage,
+            Quantity::Current => sample.current,
+        }
+    }
+
+    fn setpoint(&self, sample: &Sample) -> f32 {
+        match self {
+            Quantity::Voltage => sample.voltage_set,
+            Quantity::Current => sample.current_set,
+        }
+    }
+
+    fn limit(&self, sample: &Sample) -> Option<f32> {
+        match self {
+            Quantity::Voltage => sample.overvoltage_trip,
+            Quantity::Current => sample.overcurrent_trip,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartMarker {
+    pub x: f32,
+    pub kind: MarkerKind,
+    pub label: String,
+}
+
+/// Chart scaled into a `width` x `height` view box, y grows downwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    /// Points of an SVG polyline.
+    pub points: String,
+    /// Top of the scale in the unit of the quantity.
+    pub max: f32,
+    pub setpoint: Option<f32>,
+    /// Trip threshold, if it fits into the scale.
+    pub limit: Option<f32>,
+    pub markers: Vec<ChartMarker>,
+}
+
+/// Scales the window of the history ending at `now` into a chart.
+///
+/// Samples are reduced to the minimum and maximum of each horizontal pixel,
+/// so that short spikes remain visible in long windows.
+pub fn chart(
+    history: &ChannelHistory,
+    quantity: Quantity,
+    window: Duration,
+    now: Instant,
+    width: f32,
+    height: f32,
+) -> Chart {
+    let start = now.checked_sub(window).unwrap_or(now);
+    let samples: Vec<&Sample> = history
+        .samples
+        .iter()
+        .filter(|sample| sample.time >= start)
+        .collect();
+    let last = samples.last();
+
+    let setpoint = last.map(|sample| quantity.setpoint(sample));
+    let data_max = samples
+        .iter()
+        .map(|sample| quantity.value(sample))
+        .chain(setpoint)
+        .fold(0.0f32, f32::max);
+    let max = if data_max > 0.0 { data_max * 1.1 } else { 1.0 };
+    let limit = last
+        .and_then(|sample| quantity.limit(sample))
+        .filter(|limit| *limit <= max);
+
+    let x = |time: Instant| {
+        let elapsed = time.saturating_duration_since(start).as_secs_f32();
+        (elapsed / window.as_secs_f32() * width).clamp(0.0, width)
+    };
+    let y = |value: f32| height - (value / max * height).clamp(0.0, height);
+
+    // (pixel, min, max) of the samples in each pixel column
+    let mut columns: Vec<(u32, f32, f32)> = Vec::new();
+    for sample in &samples {
+        let column = x(sample.time) as u32;
+        let value = quantity.value(sample);
+        match columns.last_mut() {
+            Some((last, min, max)) if *last == column => {
+                *min = min.min(value);
+                *max = max.max(value);
+            }
+            _ => columns.push((column, value, value)),
+        }
+    }
+    let points = columns
+        .iter()
+        .flat_map(|&(column, min, max)| {
+            let px = column as f32;
+            match min == max {
+                true => vec![(px, y(min))],
+                false => vec![(px, y(min)), (px, y(max))],
+            }
+        })
+        .map(|(x, y)| format!("{x:.1},{y:.1}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let markers = history
+        .markers
+        .iter()
+        .filter(|marker| marker.time >= start)
+        .map(|marker| ChartMarker {
+            x: x(marker.time),
+            kind: marker.kind,
+            label: marker.label.clone(),
+        })
+        .collect();
+
+    Chart {
+        points,
+        max,
+        setpoint,
+        limit,
+        markers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(enabled: bool, voltage: f32, status: LimitEventStatus) -> Channel {
+        Channel {
+            overvoltage_trip: Some(5.2),
+            overcurrent_trip: Some(30.0),
+            status,
+            ..crate::test_support::channel(enabled, voltage, 0.1)
+        }
+    }
+
+    #[test]
+    fn test_markers_and_retention() {
+        let start = Instant::now();
+        let mut history = ChannelHistory::default();
+        history.record(start, &channel(false, 0.0, LimitEventStatus::empty()));
+        history.record(
+            start + Duration::from_secs(1),
+            &channel(true, 5.0, LimitEventStatus::empty()),
+        );
+        let trip = LimitEventStatus::OVER_VOLTAGE_TRIP | LimitEventStatus::VOLTAGE_LIMIT;
+        history.record(start + Duration::from_secs(2), &channel(false, 0.0, trip));
+        history.record(start + Duration::from_secs(3), &channel(false, 0.0, trip));
+
+        let kinds: Vec<_> = history
+            .markers
+            .iter()
+            .map(|m| (m.kind, m.label.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (MarkerKind::On, "ON"),
+                (MarkerKind::Off, "OFF"),
+                (MarkerKind::Trip, "OVER_VOLTAGE_TRIP"),
+            ]
+        );
+
+        history.record(
+            start + RETENTION + Duration::from_millis(1500),
+            &channel(false, 0.0, trip),
+        );
+        assert_eq!(history.samples.len(), 3);
+        assert_eq!(history.markers.len(), 2);
+    }
+
+    #[test]
+    fn test_chart() {
+        let start = Instant::now();
+        let mut history = ChannelHistory::default();
+        for (secs, voltage) in [(0, 4.0), (5, 5.0), (5, 2.0), (10, 5.0)] {
+            history.record(
+                start + Duration::from_secs(secs),
+                &channel(true, voltage, LimitEventStatus::empty()),
+            );
+        }
+
+        let now = start + Duration::from_secs(10);
+        let chart = chart(
+            &history,
+            Quantity::Voltage,
+            Duration::from_secs(10),
+            now,
+            100.0,
+            55.0,
+        );
+        assert_eq!(chart.max, 5.5);
+        assert_eq!(chart.setpoint, Some(5.0));
+        assert_eq!(chart.limit, Some(5.2));
+        // both extremes of the samples in the middle column are kept
+        assert_eq!(chart.points, "0.0,15.0 50.0,35.0 50.0,5.0 100.0,5.0");
+
+        let chart = super::chart(
+            &history,
+            Quantity::Current,
+            Duration::from_secs(10),
+            now,
+            100.0,
+            55.0,
+        );
+        // the trip far above the readings does not squash the chart
+        assert_eq!(chart.limit, None);
+    }
+}
@@ -0,0 +1,16 @@
+//! Driver and configuration of the power supplies, usable without the GUI.
+
+pub mod config;
+pub mod discovery;
+pub mod line_codec;
+pub mod models;
+pub mod mx100qp;
+pub mod simulator;
+pub mod transcript;
+pub mod transport;
+
+use std::path::PathBuf;
+
+pub fn get_config_dir() -> PathBuf {
+    dirs::config_dir().unwrap().join("power-supply-control")
+}
@@ -4,7 +4,28 @@ use bytes::BytesMut;
 use log::trace;
 use tokio_util::codec::{Decoder, Encoder};
 
-pub struct LineCodec;
+use crate::transcript::{Direction, TranscriptWriter};
+
+#[derive(Default)]
+pub struct LineCodec {
+    transcript: Option<TranscriptWriter>,
+}
+
+impl LineCodec {
+    /// Codec recording every sent and received line to the transcript.
+    pub fn recording(transcript: TranscriptWriter) -> Self {
+        Self {
+            transcript: Some(transcript),
+        }
+    }
+
+    /// Writes out the buffered transcript lines.
+    pub fn flush_transcript(&mut self) {
+        if let Some(transcript) = &mut self.transcript {
+            transcript.flush();
+        }
+    }
+}
 
 impl Decoder for LineCodec {
     type Item = String;
@@ -18,6 +39,9 @@ impl Decoder for LineCodec {
                 Ok(s) => {
                     let received = s.trim().to_string();
                     trace!("Received {received}");
+                    if let Some(transcript) = &mut self.transcript {
+                        transcript.record(Direction::Received, &received);
+                    }
                     Ok(Some(received))
                 }
                 Err(_) => Err(io::Error::other("Invalid String")),
@@ -34,6 +58,9 @@ impl Encoder<String> for LineCodec {
         _dst.write_str(&_item).unwrap();
         _dst.write_char('\n').unwrap();
         trace!("Sending {_item}");
+        if let Some(transcript) = &mut self.transcript {
+            transcript.record(Direction::Sent, &_item);
+        }
         Ok(())
     }
 }
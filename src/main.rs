@@ -1,17 +1,31 @@
 #![allow(non_snake_case)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-pub mod components;
-pub mod config;
-pub mod line_codec;
-pub mod mx100qp;
+#[cfg(feature = "api")]
+mod api;
+mod components;
+#[cfg(feature = "datalog")]
+mod datalog;
+mod energy;
+mod history;
+#[cfg(feature = "api")]
+mod metrics;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod passthrough;
+mod sequencer;
+mod stats;
+mod supplies;
+#[cfg(test)]
+mod test_support;
 
 use dioxus::{
     desktop::{Config, WindowBuilder},
     prelude::*,
 };
 use dioxus_desktop::{LogicalSize, tao};
-use log::{LevelFilter, info};
+use futures::future::{FutureExt, LocalBoxFuture, join_all};
+use log::{LevelFilter, error, info};
 use log4rs::{
     append::{
         console::{ConsoleAppender, Target},
@@ -20,14 +34,11 @@ use log4rs::{
     config::{Appender, Root},
     encode::pattern::PatternEncoder,
 };
-use std::{path::PathBuf, str::FromStr};
+use power_supply_control::{config::AppConfig, get_config_dir};
+use std::str::FromStr;
 use tracing::Level;
 
-use crate::components::app::AppComponent;
-
-fn get_config_dir() -> PathBuf {
-    dirs::config_dir().unwrap().join("power-supply-control")
-}
+use crate::{components::app::AppComponent, supplies::Supplies};
 
 fn appconfig_default() -> Config {
     Config::default().with_window(
@@ -58,6 +69,25 @@ fn appconfig() -> Config {
         .with_disable_context_menu(true)
 }
 
+/// Runs the servers sharing the supplies in their own thread, so that clients do not wait for the GUI.
+fn spawn_servers(appconfig: AppConfig, supplies: Supplies) {
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let data = appconfig.data;
+        let servers: Vec<LocalBoxFuture<()>> = vec![
+            passthrough::serve(data.power_supplies, supplies.clone()).boxed_local(),
+            #[cfg(feature = "api")]
+            api::serve(data.api, supplies.clone()).boxed_local(),
+            #[cfg(feature = "mqtt")]
+            mqtt::serve(data.mqtt, supplies).boxed_local(),
+        ];
+        runtime.block_on(join_all(servers));
+    });
+}
+
 fn main() {
     std::fs::create_dir_all(get_config_dir()).unwrap();
 
@@ -95,7 +125,21 @@ fn main() {
         env!("VERGEN_GIT_DESCRIBE")
     );
 
+    // an invalid config is not replaced by the default one, it would be overwritten on save
+    let config = match AppConfig::load_from_file(get_config_dir().join("config.json")) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    let supplies = Supplies::default();
+    spawn_servers(config.clone(), supplies.clone());
+
     LaunchBuilder::new()
         .with_cfg(appconfig())
+        .with_context(config)
+        .with_context(supplies)
         .launch(AppComponent)
 }
@@ -0,0 +1,233 @@
+//! Prometheus exposition of the supply states served by the API on `/metrics`.
+
+use std::fmt::Write;
+
+use power_supply_control::mx100qp::LimitEventStatus;
+
+use crate::{
+    stats::{StatsSummary, Summary},
+    supplies::{ChannelState, SupplyState},
+};
+
+/// Escapes a label value of the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn supply_labels(supply: &SupplyState) -> String {
+    format!(
+        "supply=\"{}\",name=\"{}\"",
+        escape(&supply.id),
+        escape(&supply.name)
+    )
+}
+
+fn channel_labels(supply: &SupplyState, channel: &ChannelState) -> String {
+    format!(
+        "{},channel=\"{}\",channel_name=\"{}\"",
+        supply_labels(supply),
+        channel.channel,
+        escape(&channel.name)
+    )
+}
+
+/// Gauge with its samples, written with a single header.
+struct Gauge<'a> {
+    out: &'a mut String,
+    name: &'a str,
+}
+
+impl<'a> Gauge<'a> {
+    fn new(out: &'a mut String, name: &'a str, help: &str) -> Self {
+        writeln!(out, "# HELP power_supply_{name} {help}").unwrap();
+        writeln!(out, "# TYPE power_supply_{name} gauge").unwrap();
+        Self { out, name }
+    }
+
+    fn sample(&mut self, labels: &str, value: impl Into<f64>) {
+        let name = self.name;
+        writeln!(self.out, "power_supply_{name}{{{labels}}} {}", value.into()).unwrap();
+    }
+}
+
+type ChannelValue = fn(&ChannelState) -> Option<f64>;
+
+const CHANNEL_GAUGES: [(&str, &str, ChannelValue); 7] = [
+    ("voltage_volts", "Voltage readback.", |ch| {
+        Some(ch.voltage.into())
+    }),
+    ("voltage_setpoint_volts", "Voltage setpoint.", |ch| {
+        Some(ch.voltage_set.into())
+    }),
+    ("current_amps", "Current readback.", |ch| {
+        Some(ch.current.into())
+    }),
+    ("current_setpoint_amps", "Current setpoint.", |ch| {
+        Some(ch.current_set.into())
+    }),
+    ("output_enabled", "Whether the output is on.", |ch| {
+        Some(u8::from(ch.enabled).into())
+    }),
+    (
+        "ovp_volts",
+        "Over-voltage trip, absent when disabled.",
+        |ch| ch.ovp.map(f64::from),
+    ),
+    (
+        "ocp_amps",
+        "Over-current trip, absent when disabled.",
+        |ch| ch.ocp.map(f64::from),
+    ),
+];
+
+type StatsValue = fn(&StatsSummary) -> Summary;
+
+const STATS_GAUGES: [(&str, &str, StatsValue); 2] = [
+    (
+        "voltage_stats_volts",
+        "Voltage statistics since their reset in the GUI.",
+        |stats| stats.voltage,
+    ),
+    (
+        "current_stats_amps",
+        "Current statistics since their reset in the GUI.",
+        |stats| stats.current,
+    ),
+];
+
+/// Renders the states in the Prometheus text format.
+///
+/// Channels of disconnected supplies are left out, their last readings are stale.
+pub fn render(supplies: &[SupplyState]) -> String {
+    let mut out = String::new();
+
+    let mut gauge = Gauge::new(&mut out, "up", "Whether the supply is connected.");
+    for supply in supplies {
+        gauge.sample(&supply_labels(supply), u8::from(supply.connected));
+    }
+
+    let mut gauge = Gauge::new(
+        &mut out,
+        "poll_duration_seconds",
+        "Duration of the last poll of all channels.",
+    );
+    for supply in supplies.iter().filter(|supply| supply.connected) {
+        if let Some(duration) = supply.poll_duration {
+            gauge.sample(&supply_labels(supply), duration);
+        }
+    }
+
+    let connected: Vec<&SupplyState> = supplies.iter().filter(|s| s.connected).collect();
+    for (name, help, value) in CHANNEL_GAUGES {
+        let mut gauge = Gauge::new(&mut out, name, help);
+        for supply in &connected {
+            for channel in &supply.channels {
+                if let Some(value) = value(channel) {
+                    gauge.sample(&channel_labels(supply, channel), value);
+                }
+            }
+        }
+    }
+
+    for (name, help, summary) in STATS_GAUGES {
+        let mut gauge = Gauge::new(&mut out, name, help);
+        for supply in &connected {
+            for channel in &supply.channels {
+                let Some(stats) = &channel.stats else {
+                    continue;
+                };
+                let labels = channel_labels(supply, channel);
+                let summary = summary(stats);
+                for (stat, value) in [
+                    ("min", summary.min),
+                    ("max", summary.max),
+                    ("mean", summary.mean),
+                    ("rms", summary.rms),
+                ] {
+                    gauge.sample(&format!("{labels},stat=\"{stat}\""), value);
+                }
+            }
+        }
+    }
+
+    let mut gauge = Gauge::new(&mut out, "trip", "Whether the trip is active.");
+    for supply in &connected {
+        for channel in &supply.channels {
+            let labels = channel_labels(supply, channel);
+            for (trip, _) in LimitEventStatus::TRIPS.iter_names() {
+                let active = channel.status.contains(&trip);
+                gauge.sample(&format!("{labels},trip=\"{trip}\""), u8::from(active));
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_render() {
+        let mut supply = test_support::supply(&["core \"A\""]);
+        supply.poll_duration = Some(0.02);
+        supply.channels[0] = ChannelState {
+            enabled: true,
+            voltage: 1.5,
+            voltage_set: 1.8,
+            current: 0.25,
+            current_set: 1.0,
+            ovp: Some(2.0),
+            status: vec!["CURRENT_LIMIT", "OVER_CURRENT_TRIP"],
+            stats: Some(StatsSummary {
+                samples: 2,
+                voltage: Summary {
+                    min: 1.5,
+                    max: 1.5,
+                    mean: 1.5,
+                    rms: 1.5,
+                },
+                current: Summary {
+                    min: 0.25,
+                    max: 3.0,
+                    mean: 1.625,
+                    rms: 2.0,
+                },
+            }),
+            ..supply.channels[0].clone()
+        };
+        let mut supplies = vec![supply];
+        let labels = r#"supply="SIM",name="bench",channel="1",channel_name="core \"A\"""#;
+
+        let metrics = render(&supplies);
+        assert!(metrics.contains("# TYPE power_supply_up gauge\n"));
+        assert!(metrics.contains("power_supply_up{supply=\"SIM\",name=\"bench\"} 1\n"));
+        assert!(
+            metrics.contains(
+                "power_supply_poll_duration_seconds{supply=\"SIM\",name=\"bench\"} 0.02\n"
+            )
+        );
+        assert!(metrics.contains(&format!("power_supply_voltage_volts{{{labels}}} 1.5\n")));
+        assert!(metrics.contains(&format!("power_supply_ovp_volts{{{labels}}} 2\n")));
+        assert!(!metrics.contains("power_supply_ocp_amps{"));
+        assert!(metrics.contains(&format!(
+            "power_supply_current_stats_amps{{{labels},stat=\"max\"}} 3\n"
+        )));
+        assert!(metrics.contains(&format!(
+            "power_supply_trip{{{labels},trip=\"OVER_CURRENT_TRIP\"}} 1\n"
+        )));
+        assert!(metrics.contains(&format!(
+            "power_supply_trip{{{labels},trip=\"OVER_VOLTAGE_TRIP\"}} 0\n"
+        )));
+
+        supplies[0].connected = false;
+        let metrics = render(&supplies);
+        assert!(metrics.contains("power_supply_up{supply=\"SIM\",name=\"bench\"} 0\n"));
+        assert!(!metrics.contains("power_supply_voltage_volts{"));
+    }
+}
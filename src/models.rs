@@ -0,0 +1,353 @@
+use bitflags::bitflags;
+
+#[derive(Debug, PartialEq)]
+pub struct VRange {
+    pub voltage: f32,
+    pub current: f32,
+}
+
+impl std::fmt::Display for VRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}V/{}A", self.voltage, self.current)
+    }
+}
+
+/// Placeholder for range values the device does not use.
+const NO_RANGE: VRange = VRange {
+    voltage: 0.0,
+    current: 0.0,
+};
+
+/// Voltage tracking mode selected by `CONFIG <N>`.
+#[derive(Debug, PartialEq)]
+pub struct TrackingMode {
+    pub config: u8,
+    pub label: &'static str,
+    /// Pairs of (master, slave) channel indexes.
+    pub pairs: &'static [(u8, u8)],
+}
+
+bitflags! {
+    /// Optional commands supported by the model.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Features: u8 {
+        /// `OPALL` switching all outputs at once.
+        const ALL_OUTPUTS = 1;
+        /// `ONACTION`/`ONDELAY` delayed multi-on.
+        const MULTI_ON = 1 << 1;
+        /// `*SAV`/`*RCL` stores holding all outputs.
+        const SETUP_STORES = 1 << 2;
+        /// `SAV<N>`/`RCL<N>` stores holding a single output.
+        const CHANNEL_SETUP_STORES = 1 << 3;
+        /// `OFFACTION`/`OFFDELAY` delayed multi-off.
+        const MULTI_OFF = 1 << 4;
+    }
+}
+
+/// Capabilities of a supported power supply model.
+#[derive(Debug, PartialEq)]
+pub struct Model {
+    /// Model name as reported by `*IDN?`.
+    pub name: &'static str,
+    pub channels: u8,
+    /// Command selecting the range, e.g. `VRANGE`, `None` for single range models.
+    pub range_command: Option<&'static str>,
+    /// Ranges of each channel indexed by the value sent with `range_command`.
+    pub ranges: &'static [&'static [VRange]],
+    /// Voltage tracking modes, the first one is independent operation.
+    pub tracking: &'static [TrackingMode],
+    /// Number of setup stores for each kind of store.
+    pub setup_stores: u8,
+    pub features: Features,
+    /// Pairs of (tracking config, channel number) in which the device does not
+    /// answer `OVP<N>?`/`OCP<N>?`.
+    pub silent_trips: &'static [(u8, u8)],
+}
+
+impl Model {
+    /// Returns true if the range of the channel can be selected.
+    pub fn has_ranges(&self, ch: u8) -> bool {
+        self.range_command.is_some() && self.selectable_ranges(ch).count() > 1
+    }
+
+    /// Returns ranges of the channel together with the value selecting them.
+    pub fn selectable_ranges(&self, ch: u8) -> impl Iterator<Item = (u8, &VRange)> {
+        self.ranges
+            .get(ch as usize)
+            .copied()
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| **range != NO_RANGE)
+            .map(|(i, range)| (i as u8, range))
+    }
+
+    /// Selects the lowest voltage range able to supply both voltage and current.
+    pub fn auto_vrange(&self, ch: u8, voltage: f32, current: f32) -> Option<u8> {
+        if !self.has_ranges(ch) {
+            return None;
+        }
+
+        let mut candidates: Vec<(u8, &VRange)> = self
+            .selectable_ranges(ch)
+            .filter(|(_, range)| voltage <= range.voltage && current <= range.current)
+            .collect();
+        candidates.sort_by_key(|(_, range)| range.voltage as u8);
+        candidates.first().map(|(i, _)| *i)
+    }
+
+    pub fn tracking_mode(&self, config: u8) -> Option<&TrackingMode> {
+        self.tracking.iter().find(|mode| mode.config == config)
+    }
+
+    /// Tracking configuration used when the configured one is not supported.
+    pub fn default_tracking(&self) -> u8 {
+        self.tracking.first().map_or(0, |mode| mode.config)
+    }
+
+    pub fn supports(&self, features: Features) -> bool {
+        self.features.contains(features)
+    }
+
+    /// Returns true if trip thresholds of the channel number cannot be read in the tracking config.
+    pub fn has_silent_trips(&self, config: u8, ch: u8) -> bool {
+        self.silent_trips.contains(&(config, ch))
+    }
+}
+
+const MX100QP_RANGE_A: &[VRange] = &[
+    NO_RANGE,
+    VRange {
+        voltage: 35.0,
+        current: 3.0,
+    },
+    VRange {
+        voltage: 16.0,
+        current: 6.0,
+    },
+    VRange {
+        voltage: 35.0,
+        current: 6.0,
+    },
+];
+
+const MX100QP_RANGE_B: &[VRange] = &[
+    NO_RANGE,
+    VRange {
+        voltage: 35.0,
+        current: 3.0,
+    },
+    VRange {
+        voltage: 70.0,
+        current: 1.5,
+    },
+    VRange {
+        voltage: 70.0,
+        current: 3.0,
+    },
+];
+
+pub const MX100QP: Model = Model {
+    name: "MX100QP",
+    channels: 4,
+    range_command: Some("VRANGE"),
+    ranges: &[
+        MX100QP_RANGE_A,
+        MX100QP_RANGE_A,
+        MX100QP_RANGE_B,
+        MX100QP_RANGE_B,
+    ],
+    tracking: &[
+        TrackingMode {
+            config: 0,
+            label: "V1 V2 V3 V4",
+            pairs: &[],
+        },
+        TrackingMode {
+            config: 1,
+            label: "V1=V2 V3 V4",
+            pairs: &[(0, 1)],
+        },
+        TrackingMode {
+            config: 2,
+            label: "V1 V2 V3=V4",
+            pairs: &[(2, 3)],
+        },
+        TrackingMode {
+            config: 3,
+            label: "V1=V2 V3=V4",
+            pairs: &[(0, 1), (2, 3)],
+        },
+    ],
+    setup_stores: 50,
+    features: Features::all(),
+    silent_trips: &[(3, 2)],
+};
+
+const TWO_CHANNEL_TRACKING: &[TrackingMode] = &[
+    TrackingMode {
+        config: 2,
+        label: "V1 V2",
+        pairs: &[],
+    },
+    TrackingMode {
+        config: 0,
+        label: "V1=V2",
+        pairs: &[(0, 1)],
+    },
+];
+
+const CPX400_RANGE: &[VRange] = &[VRange {
+    voltage: 60.0,
+    current: 20.0,
+}];
+
+pub const CPX400DP: Model = Model {
+    name: "CPX400DP",
+    channels: 2,
+    range_command: None,
+    ranges: &[CPX400_RANGE, CPX400_RANGE],
+    tracking: TWO_CHANNEL_TRACKING,
+    setup_stores: 10,
+    features: Features::ALL_OUTPUTS.union(Features::CHANNEL_SETUP_STORES),
+    silent_trips: &[],
+};
+
+const QL355_RANGE: &[VRange] = &[
+    VRange {
+        voltage: 15.0,
+        current: 5.0,
+    },
+    VRange {
+        voltage: 35.0,
+        current: 3.0,
+    },
+    VRange {
+        voltage: 35.0,
+        current: 0.5,
+    },
+];
+
+const QL355_AUX_RANGE: &[VRange] = &[VRange {
+    voltage: 6.0,
+    current: 3.0,
+}];
+
+pub const QL355TP: Model = Model {
+    name: "QL355TP",
+    channels: 3,
+    range_command: Some("RANGE"),
+    ranges: &[QL355_RANGE, QL355_RANGE, QL355_AUX_RANGE],
+    tracking: TWO_CHANNEL_TRACKING,
+    setup_stores: 10,
+    features: Features::ALL_OUTPUTS.union(Features::CHANNEL_SETUP_STORES),
+    silent_trips: &[],
+};
+
+const PL303_RANGE: &[VRange] = &[
+    NO_RANGE,
+    VRange {
+        voltage: 30.0,
+        current: 0.5,
+    },
+    VRange {
+        voltage: 30.0,
+        current: 3.0,
+    },
+];
+
+pub const PL303_P: Model = Model {
+    name: "PL303-P",
+    channels: 1,
+    range_command: Some("IRANGE"),
+    ranges: &[PL303_RANGE],
+    tracking: &[],
+    setup_stores: 10,
+    features: Features::CHANNEL_SETUP_STORES,
+    silent_trips: &[],
+};
+
+pub const PL303QMD_P: Model = Model {
+    name: "PL303QMD-P",
+    channels: 2,
+    range_command: Some("IRANGE"),
+    ranges: &[PL303_RANGE, PL303_RANGE],
+    tracking: TWO_CHANNEL_TRACKING,
+    setup_stores: 10,
+    features: Features::ALL_OUTPUTS.union(Features::CHANNEL_SETUP_STORES),
+    silent_trips: &[],
+};
+
+const PL601_RANGE: &[VRange] = &[
+    NO_RANGE,
+    VRange {
+        voltage: 60.0,
+        current: 0.5,
+    },
+    VRange {
+        voltage: 60.0,
+        current: 1.5,
+    },
+];
+
+pub const PL601_P: Model = Model {
+    name: "PL601-P",
+    channels: 1,
+    range_command: Some("IRANGE"),
+    ranges: &[PL601_RANGE],
+    tracking: &[],
+    setup_stores: 10,
+    features: Features::CHANNEL_SETUP_STORES,
+    silent_trips: &[],
+};
+
+pub const MODELS: &[&Model] = &[
+    &MX100QP,
+    &CPX400DP,
+    &QL355TP,
+    &PL303_P,
+    &PL303QMD_P,
+    &PL601_P,
+];
+
+/// Finds the model by name reported in `*IDN?`.
+pub fn find(name: &str) -> Option<&'static Model> {
+    MODELS
+        .iter()
+        .find(|model| model.name.eq_ignore_ascii_case(name.trim()))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_vrange() {
+        assert_eq!(MX100QP.auto_vrange(0, 0.0, 0.0), Some(2));
+        assert_eq!(MX100QP.auto_vrange(0, 1.0, 2.0), Some(2));
+        assert_eq!(MX100QP.auto_vrange(0, 35.0, 0.1), Some(1));
+        assert_eq!(MX100QP.auto_vrange(0, 35.0, 3.0), Some(1));
+        assert_eq!(MX100QP.auto_vrange(0, 35.0, 5.0), Some(3));
+        assert_eq!(MX100QP.auto_vrange(0, 35.0, 6.0), Some(3));
+        assert_eq!(MX100QP.auto_vrange(0, 70.0, 0.1), None);
+
+        assert_eq!(QL355TP.auto_vrange(0, 12.0, 4.0), Some(0));
+        assert_eq!(QL355TP.auto_vrange(2, 5.0, 1.0), None);
+        assert_eq!(CPX400DP.auto_vrange(0, 12.0, 4.0), None);
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("MX100QP"), Some(&MX100QP));
+        assert_eq!(find(" cpx400dp"), Some(&CPX400DP));
+        assert_eq!(find("MX100TP"), None);
+    }
+
+    #[test]
+    fn test_silent_trips() {
+        assert!(MX100QP.has_silent_trips(3, 2));
+        assert!(!MX100QP.has_silent_trips(1, 2));
+        assert!(!CPX400DP.has_silent_trips(3, 2));
+    }
+}
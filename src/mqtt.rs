@@ -0,0 +1,305 @@
+//! MQTT client publishing telemetry of the supplies and mapping command topics onto their actions.
+//!
+//! Topics below the configured prefix:
+//! - `status` is `online` while the GUI is connected to the broker, `offline` otherwise (retained)
+//! - `<supply>/status` is `online` while the supply is connected, `offline` otherwise (retained),
+//!   it is only valid while `status` is `online`, the last will covers just the global topic
+//! - `<supply>/<channel>/voltage`, `current` readbacks, `output` and active `trips` (retained)
+//! - `<supply>/<channel>/trip` each newly reported trip
+//! - `<supply>/set/output` and `<supply>/<channel>/set/{output,voltage,current}` commands
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use power_supply_control::{config::MqttConfig, mx100qp::LimitEventStatus};
+use rumqttc::{AsyncClient, Event as MqttEvent, LastWill, MqttOptions, Packet, QoS};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    components::power_supply::{ChannelSelection, PowerSupplyAction},
+    supplies::{Event, Frame, Supplies, SupplyState},
+};
+
+/// Delay before reconnecting to the broker after a connection error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Topic level of a supply or channel name.
+fn topic_level(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '+' | '#' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn availability(online: bool) -> &'static str {
+    if online { "online" } else { "offline" }
+}
+
+/// Finds the action of a command topic below the prefix, e.g. `bench/fan/set/voltage`.
+fn parse_command(
+    supplies: &[SupplyState],
+    topic: &str,
+    payload: &str,
+) -> Result<(String, PowerSupplyAction), String> {
+    let levels: Vec<&str> = topic.split('/').collect();
+    let (supply, channel, command) = match levels[..] {
+        [supply, "set", command] => (supply, None, command),
+        [supply, channel, "set", command] => (supply, Some(channel), command),
+        _ => return Err(format!("Unknown command topic {topic}")),
+    };
+
+    let supply = supplies
+        .iter()
+        .find(|state| topic_level(&state.name) == supply)
+        .ok_or_else(|| format!("Unknown power supply {supply}"))?;
+    let channel = match channel {
+        Some(channel) => Some(
+            supply
+                .channels
+                .iter()
+                .find(|ch| topic_level(&ch.name) == channel || ch.channel.to_string() == channel)
+                .map(|ch| ch.channel - 1)
+                .ok_or_else(|| format!("Unknown channel {channel} of {}", supply.name))?,
+        ),
+        None => None,
+    };
+    let selection = channel.map_or(ChannelSelection::AllChannels, ChannelSelection::Channel);
+
+    let payload = payload.trim();
+    let value = || {
+        payload
+            .parse::<f32>()
+            .map_err(|_| format!("Invalid value {payload:?} of {topic}"))
+    };
+    let action = match (command, channel) {
+        ("output", _) if payload.eq_ignore_ascii_case("on") => PowerSupplyAction::On(selection),
+        ("output", _) if payload.eq_ignore_ascii_case("off") => {
+            PowerSupplyAction::Off(selection, Instant::now())
+        }
+        ("voltage", Some(ch)) => PowerSupplyAction::SetVoltage(ch, value()?),
+        ("current", Some(ch)) => PowerSupplyAction::SetCurrent(ch, value()?),
+        _ => return Err(format!("Invalid command {payload:?} of {topic}")),
+    };
+    Ok((supply.id.clone(), action))
+}
+
+struct Publisher {
+    client: AsyncClient,
+    prefix: String,
+    interval: Duration,
+    /// Time of the last published readings of each supply.
+    published: HashMap<String, Instant>,
+    /// Retained status topics of supplies published since the start.
+    announced: HashSet<String>,
+}
+
+impl Publisher {
+    fn publish(&self, topic: String, payload: String, retain: bool) {
+        if let Err(err) = self
+            .client
+            .try_publish(topic, QoS::AtMostOnce, retain, payload)
+        {
+            warn!("MQTT publish dropped: {err}");
+        }
+    }
+
+    fn supply_topic(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, topic_level(name))
+    }
+
+    fn publish_status(&mut self, name: &str, connected: bool) {
+        let topic = format!("{}/status", self.supply_topic(name));
+        self.publish(topic.clone(), availability(connected).into(), true);
+        self.announced.insert(topic);
+    }
+
+    fn publish_frame(&mut self, frame: Frame) {
+        let topic = self.supply_topic(&frame.name);
+        match frame.event {
+            Event::Connected { .. } => self.publish_status(&frame.name, true),
+            Event::Disconnected { .. } => self.publish_status(&frame.name, false),
+            Event::Trip {
+                channel_name, trip, ..
+            } => {
+                let topic = format!("{topic}/{}/trip", topic_level(&channel_name));
+                self.publish(topic, trip.to_string(), false);
+            }
+            Event::Readings { channels, .. } => {
+                let now = Instant::now();
+                if let Some(last) = self.published.get(&frame.supply)
+                    && now.duration_since(*last) < self.interval
+                {
+                    return;
+                }
+                self.published.insert(frame.supply, now);
+
+                for channel in channels {
+                    let topic = format!("{topic}/{}", topic_level(&channel.name));
+                    let output = if channel.enabled { "on" } else { "off" };
+                    let trips: Vec<&str> = channel
+                        .status
+                        .iter()
+                        .copied()
+                        .filter(|status| {
+                            LimitEventStatus::TRIPS
+                                .iter_names()
+                                .any(|(n, _)| n == *status)
+                        })
+                        .collect();
+                    self.publish(
+                        format!("{topic}/voltage"),
+                        channel.voltage.to_string(),
+                        false,
+                    );
+                    self.publish(
+                        format!("{topic}/current"),
+                        channel.current.to_string(),
+                        false,
+                    );
+                    self.publish(format!("{topic}/output"), output.to_string(), true);
+                    self.publish(format!("{topic}/trips"), trips.join(","), true);
+                    if let Some(stats) = &channel.stats {
+                        let stats = serde_json::to_string(stats).unwrap_or_default();
+                        self.publish(format!("{topic}/stats"), stats, false);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Announces the client and supplies and subscribes to commands after (re)connecting.
+    ///
+    /// Retained status of supplies which are gone since they were announced is cleared,
+    /// so that they do not stay `online`.
+    fn on_connected(&mut self, supplies: &Supplies) {
+        info!("MQTT connected");
+        let prefix = self.prefix.clone();
+        self.publish(format!("{prefix}/status"), availability(true).into(), true);
+
+        let stale = std::mem::take(&mut self.announced);
+        for state in supplies.states() {
+            self.publish_status(&state.name, state.connected);
+        }
+        for topic in stale.difference(&self.announced) {
+            self.publish(topic.clone(), String::new(), true);
+        }
+
+        for filter in [format!("{prefix}/+/set/+"), format!("{prefix}/+/+/set/+")] {
+            if let Err(err) = self.client.try_subscribe(filter, QoS::AtLeastOnce) {
+                warn!("MQTT subscribe failed: {err}");
+            }
+        }
+    }
+}
+
+/// Runs the MQTT client if enabled in the configuration.
+pub async fn serve(config: MqttConfig, supplies: Supplies) {
+    if !config.enabled {
+        return;
+    }
+
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        format!("{}/status", config.prefix),
+        availability(false),
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 256);
+    let mut publisher = Publisher {
+        client,
+        prefix: config.prefix.clone(),
+        interval: Duration::from_millis(config.interval_ms),
+        published: HashMap::new(),
+        announced: HashSet::new(),
+    };
+    let mut events = supplies.subscribe();
+    let command_prefix = format!("{}/", config.prefix);
+
+    // the event loop reconnects on its next poll, which is held back after a failure
+    let mut retry_at: Option<tokio::time::Instant> = None;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(retry_at.unwrap_or_else(tokio::time::Instant::now)),
+                if retry_at.is_some() => retry_at = None,
+            event = eventloop.poll(), if retry_at.is_none() => match event {
+                Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => publisher.on_connected(&supplies),
+                Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                    let Some(topic) = publish.topic.strip_prefix(&command_prefix) else {
+                        continue;
+                    };
+                    let payload = String::from_utf8_lossy(&publish.payload);
+                    let command = parse_command(&supplies.states(), topic, &payload);
+                    let result = command.and_then(|(id, action)| supplies.send(&id, action));
+                    if let Err(err) = result {
+                        warn!("MQTT command {topic} rejected: {err}");
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error!("MQTT connection to {}:{} failed: {err}", config.host, config.port);
+                    retry_at = Some(tokio::time::Instant::now() + RECONNECT_DELAY);
+                }
+            },
+            frame = events.recv() => match frame {
+                Ok(frame) => publisher.publish_frame(frame),
+                Err(RecvError::Lagged(dropped)) => {
+                    warn!("MQTT publisher lagged, {dropped} frames dropped");
+                }
+                Err(RecvError::Closed) => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn supply() -> SupplyState {
+        SupplyState {
+            name: "Bench PSU".to_string(),
+            ..test_support::supply(&["Core 1V8", "fan"])
+        }
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let supplies = [supply()];
+
+        let (id, action) =
+            parse_command(&supplies, "Bench_PSU/Core_1V8/set/voltage", "1.8").unwrap();
+        assert_eq!(id, "SIM");
+        assert!(matches!(action, PowerSupplyAction::SetVoltage(0, 1.8)));
+
+        let (_, action) = parse_command(&supplies, "Bench_PSU/2/set/output", "ON").unwrap();
+        assert!(matches!(
+            action,
+            PowerSupplyAction::On(ChannelSelection::Channel(1))
+        ));
+
+        let (_, action) = parse_command(&supplies, "Bench_PSU/set/output", "off").unwrap();
+        assert!(matches!(
+            action,
+            PowerSupplyAction::Off(ChannelSelection::AllChannels, _)
+        ));
+
+        assert!(parse_command(&supplies, "Bench_PSU/set/voltage", "5").is_err());
+        assert!(parse_command(&supplies, "Bench_PSU/fan/set/current", "much").is_err());
+        assert!(parse_command(&supplies, "Other/fan/set/output", "on").is_err());
+        assert!(parse_command(&supplies, "Bench_PSU/fan/voltage", "5").is_err());
+    }
+}
@@ -1,14 +1,18 @@
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
 
 use bitflags::bitflags;
 use futures::{SinkExt, StreamExt};
-use int_enum::IntEnum;
-use log::{debug, error};
+use log::{debug, error, info, warn};
 use tokio::time::error::Elapsed;
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
 use tokio_util::codec::{Decoder, Framed};
 
-use crate::{config::PowerSupplyConfig, line_codec::LineCodec};
+use crate::{
+    config::{Connection, PowerSupplyConfig},
+    line_codec::LineCodec,
+    models::{self, Features, Model, TrackingMode},
+    transcript::TranscriptWriter,
+    transport::{self, Transport},
+};
 
 #[derive(Debug)]
 pub struct NotMatchingId {
@@ -22,10 +26,32 @@ pub enum OpenError {
     OpenError(tokio_serial::Error),
     IOError(std::io::Error),
     IdNotMatch(NotMatchingId),
-    ProtocolError,
+    ProtocolError(ProtocolError),
+    /// Device model reported by `*IDN?` is not in the capability table.
+    UnsupportedModel(String),
     Timeout,
 }
 
+impl std::fmt::Display for OpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenError::NoDeviceFound => write!(f, "No device found"),
+            OpenError::OpenError(err) => write!(f, "Failed to open port: {err}"),
+            OpenError::IOError(err) => write!(f, "I/O error: {err}"),
+            OpenError::IdNotMatch(id) => write!(
+                f,
+                "Device id {} does not match configured {}",
+                id.device, id.configured
+            ),
+            OpenError::ProtocolError(err) => write!(f, "{err}"),
+            OpenError::UnsupportedModel(model) => write!(f, "Unsupported model {model}"),
+            OpenError::Timeout => write!(f, "Device is not responding"),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
 impl From<tokio_serial::Error> for OpenError {
     fn from(err: tokio_serial::Error) -> Self {
         OpenError::OpenError(err)
@@ -44,19 +70,150 @@ impl From<Elapsed> for OpenError {
     }
 }
 
+impl From<ProtocolError> for OpenError {
+    fn from(err: ProtocolError) -> Self {
+        OpenError::ProtocolError(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// Reply does not have the expected format.
+    UnexpectedReply {
+        query: String,
+        reply: String,
+    },
+    /// Value in the reply could not be parsed.
+    Parse {
+        query: String,
+        reply: String,
+    },
+    /// Connection was closed while waiting for the reply.
+    StreamClosed {
+        query: String,
+    },
+    /// Device did not answer in time, the link was resynchronized afterwards.
+    Timeout {
+        query: String,
+    },
+    /// Device did not answer `*IDN?` during resynchronization.
+    NotResponding,
+    /// Device rejected the command.
+    Rejected {
+        command: String,
+        error: InstrumentError,
+    },
+    /// Command is not supported by the device model.
+    Unsupported {
+        command: String,
+    },
+    /// Channel number, starting from 1, missing in the device or its configuration.
+    UnknownChannel(u8),
+    IOError(std::io::Error),
+}
+
+impl ProtocolError {
+    /// Returns true if the connection is still usable after the error.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            ProtocolError::Timeout { .. }
+                | ProtocolError::Rejected { .. }
+                | ProtocolError::Unsupported { .. }
+                | ProtocolError::UnknownChannel(_)
+        )
+    }
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::UnexpectedReply { query, reply } => {
+                write!(f, "Unexpected reply {reply:?} to {query}")
+            }
+            ProtocolError::Parse { query, reply } => {
+                write!(f, "Failed to parse reply {reply:?} to {query}")
+            }
+            ProtocolError::StreamClosed { query } => {
+                write!(f, "Connection closed while waiting for reply to {query}")
+            }
+            ProtocolError::Timeout { query } => write!(f, "No reply to {query}"),
+            ProtocolError::NotResponding => write!(f, "Device is not responding"),
+            ProtocolError::Rejected { command, error } => {
+                write!(f, "Command {command} rejected: {error}")
+            }
+            ProtocolError::Unsupported { command } => {
+                write!(f, "Command {command} is not supported by the device")
+            }
+            ProtocolError::UnknownChannel(ch) => write!(f, "Unknown channel {ch}"),
+            ProtocolError::IOError(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(err: std::io::Error) -> Self {
+        ProtocolError::IOError(err)
+    }
+}
+
 #[derive(Debug)]
 pub enum MultiChannelOn {
     Disabled,
     Delay(u32),
 }
 
+#[derive(Debug)]
+pub enum MultiChannelOff {
+    Disabled,
+    Delay(u32),
+}
+
+type Protocol = Framed<Box<dyn Transport>, LineCodec>;
+
+/// Default deadline for a reply to a single query.
+pub const QUERY_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Default number of queries sent before reading their replies.
+pub const PIPELINE_DEPTH: usize = 8;
+
+/// Deadline for the device to answer `*IDN?` when connecting.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// Time without any received line after which stale replies are considered drained.
+const RESYNC_QUIET_PERIOD: Duration = Duration::from_millis(200);
+
 pub struct Mx100qp {
-    pub protocol: Framed<SerialStream, LineCodec>,
-    status: [LimitEventStatus; 4],
+    pub protocol: Protocol,
+    id: String,
+    pub model: &'static Model,
+    timeout: Duration,
+    pipeline_depth: usize,
+    status: Vec<LimitEventStatus>,
+    /// Settings from the last poll, cleared by any command that may change them.
+    settings: Option<Settings>,
+    /// Number of queries sent but not answered yet, left over by an interrupted poll.
+    pending: usize,
+}
+
+/// Settings of a channel that change only by commands.
+struct ChannelSettings {
+    vrange: u8,
+    voltage: f32,
+    current: f32,
+    overvoltage_trip: Option<f32>,
+    overcurrent_trip: Option<f32>,
+}
+
+struct Settings {
+    voltage_tracking: u8,
+    channels: Vec<ChannelSettings>,
 }
 
 bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
     pub struct LimitEventStatus: u8 {
         const VOLTAGE_LIMIT = 1;
         const CURRENT_LIMIT = 1 << 1;
@@ -67,202 +224,460 @@ bitflags! {
     }
 }
 
-pub struct VRange {
-    pub voltage: f32,
-    pub current: f32,
+impl LimitEventStatus {
+    /// Conditions switching the output off until a trip reset.
+    pub const TRIPS: Self = Self::OVER_VOLTAGE_TRIP
+        .union(Self::OVER_CURRENT_TRIP)
+        .union(Self::TEMPERATURE_TRIP)
+        .union(Self::FAULT_TRIP);
 }
 
-#[repr(u8)]
-#[derive(IntEnum, PartialEq, Debug, Clone)]
-pub enum VoltageTracking {
-    NoTracking = 0,
-    CH0_1 = 1,
-    CH2_3 = 2,
-    CH0_1AndCH2_3 = 3,
+bitflags! {
+    /// Standard event status register read by `*ESR?`.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct EventStatus: u8 {
+        const OPERATION_COMPLETE = 1;
+        const QUERY_ERROR = 1 << 2;
+        const VERIFY_TIMEOUT_ERROR = 1 << 3;
+        const EXECUTION_ERROR = 1 << 4;
+        const COMMAND_ERROR = 1 << 5;
+        const POWER_ON = 1 << 7;
+    }
 }
 
-impl std::fmt::Display for VRange {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}V/{}A", self.voltage, self.current)
+bitflags! {
+    /// Status byte register read by `*STB?`.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct StatusByte: u8 {
+        const LIMIT_EVENT_SUMMARY_1 = 1;
+        const LIMIT_EVENT_SUMMARY_2 = 1 << 1;
+        const LIMIT_EVENT_SUMMARY_3 = 1 << 2;
+        const LIMIT_EVENT_SUMMARY_4 = 1 << 3;
+        const MESSAGE_AVAILABLE = 1 << 4;
+        const EVENT_STATUS_SUMMARY = 1 << 5;
+        const REQUEST_SERVICE = 1 << 6;
     }
 }
 
-const RANGE_A: [VRange; 4] = [
-    VRange {
-        voltage: 0.0,
-        current: 0.0,
-    },
-    VRange {
-        voltage: 35.0,
-        current: 3.0,
-    },
-    VRange {
-        voltage: 16.0,
-        current: 6.0,
-    },
-    VRange {
-        voltage: 35.0,
-        current: 6.0,
-    },
-];
+/// Numeric value sent was outside the permitted range.
+pub const EXECUTION_ERROR_RANGE: u8 = 100;
+/// Recall of a set-up store with corrupted data.
+pub const EXECUTION_ERROR_CORRUPTED_STORE: u8 = 101;
+/// Recall of an empty set-up store.
+pub const EXECUTION_ERROR_EMPTY_STORE: u8 = 102;
+/// Command is not allowed while the output is on.
+pub const EXECUTION_ERROR_OUTPUT_ON: u8 = 104;
 
-const RANGE_B: [VRange; 4] = [
-    VRange {
-        voltage: 0.0,
-        current: 0.0,
-    },
-    VRange {
-        voltage: 35.0,
-        current: 3.0,
-    },
-    VRange {
-        voltage: 70.0,
-        current: 1.5,
-    },
-    VRange {
-        voltage: 70.0,
-        current: 3.0,
-    },
-];
-
-pub const VRANGES: [[VRange; 4]; 4] = [RANGE_A, RANGE_A, RANGE_B, RANGE_B];
-
-pub fn auto_vrange(ch: u8, voltage: f32, current: f32) -> Option<u8> {
-    let mut candidates: Vec<(usize, &VRange)> = VRANGES[ch as usize]
-        .iter()
-        .enumerate()
-        .skip(1)
-        .filter_map(|(i, vrange)| {
-            if voltage <= vrange.voltage && current <= vrange.current {
-                Some((i, vrange))
-            } else {
-                None
+/// Error registers of the instrument, or the copy of them kept for a passthrough client.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ErrorRegisters {
+    /// Standard event status register, `*ESR?`.
+    pub status: u8,
+    /// Execution error register, `EER?`.
+    pub execution: u8,
+    /// Query error register, `QER?`.
+    pub query: u8,
+}
+
+impl ErrorRegisters {
+    /// Error reported by the registers, execution errors first.
+    pub fn error(&self) -> Option<InstrumentError> {
+        let status = EventStatus::from_bits_truncate(self.status);
+        if status.contains(EventStatus::EXECUTION_ERROR) {
+            Some(InstrumentError::Execution(self.execution))
+        } else if status.contains(EventStatus::COMMAND_ERROR) {
+            Some(InstrumentError::Command)
+        } else if status.contains(EventStatus::QUERY_ERROR) {
+            Some(InstrumentError::Query(self.query))
+        } else if status.contains(EventStatus::VERIFY_TIMEOUT_ERROR) {
+            Some(InstrumentError::VerifyTimeout)
+        } else {
+            None
+        }
+    }
+
+    /// Adds registers read from the instrument, keeping the older error codes unless replaced.
+    fn merge(&mut self, read: ErrorRegisters) {
+        self.status |= read.status;
+        if read.execution != 0 {
+            self.execution = read.execution;
+        }
+        if read.query != 0 {
+            self.query = read.query;
+        }
+    }
+}
+
+/// Error reported by the instrument for a rejected command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstrumentError {
+    /// Command was not recognized.
+    Command,
+    /// Command could not be executed, holds the execution error register (`EER?`).
+    Execution(u8),
+    /// Query error register (`QER?`).
+    Query(u8),
+    VerifyTimeout,
+}
+
+impl std::fmt::Display for InstrumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstrumentError::Command => write!(f, "command not recognized"),
+            InstrumentError::Execution(EXECUTION_ERROR_RANGE) => write!(f, "value out of range"),
+            InstrumentError::Execution(EXECUTION_ERROR_CORRUPTED_STORE) => {
+                write!(f, "set-up store is corrupted")
             }
-        })
-        .collect();
-    candidates.sort_by_key(|(_, vrange)| vrange.voltage as u8);
-    if candidates.is_empty() {
-        None
-    } else {
-        Some(candidates[0].0 as u8)
+            InstrumentError::Execution(EXECUTION_ERROR_EMPTY_STORE) => {
+                write!(f, "set-up store is empty")
+            }
+            InstrumentError::Execution(EXECUTION_ERROR_OUTPUT_ON) => {
+                write!(f, "not allowed while output is on")
+            }
+            InstrumentError::Execution(code @ 1..=9) => write!(f, "hardware error {code}"),
+            InstrumentError::Execution(code) => write!(f, "execution error {code}"),
+            InstrumentError::Query(code) => write!(f, "query error {code}"),
+            InstrumentError::VerifyTimeout => write!(f, "verify timeout"),
+        }
     }
 }
 
 impl Mx100qp {
     pub async fn open(config: &PowerSupplyConfig) -> Result<Self, OpenError> {
-        let port_path = find_usb(config).ok_or(OpenError::NoDeviceFound)?;
-        let port = tokio_serial::new(port_path, 9600).open_native_async()?;
-        let mut protocol = LineCodec.framed(port);
-
-        let line_result =
-            tokio::time::timeout(Duration::from_millis(5000), wait_first_line(&mut protocol))
-                .await??;
-        let device_id = line_result
-            .split_terminator(',')
-            .nth(2)
-            .ok_or(OpenError::ProtocolError)?
-            .trim();
-
-        if device_id != config.id {
-            error!("Id does not match! {} != {}", device_id, config.id);
+        let mut port = match &config.connection {
+            // adapters without a serial number are told apart by the serial number of the device
+            Connection::Usb {
+                vid,
+                pid,
+                serial_number: None,
+            } => {
+                let port_path = probe_usb(*vid, *pid, &config.id).await?;
+                let transport = transport::open_serial(&port_path)?;
+                Self::with_codec(transport, codec(config)?, &config.id).await?
+            }
+            connection => {
+                let transport = transport::open(connection).await?;
+                Self::with_codec(transport, codec(config)?, &config.id).await?
+            }
+        };
+        port.set_timeout(Duration::from_millis(config.query_timeout_ms));
+        port.set_pipeline_depth(config.poll.pipeline_depth);
+        Ok(port)
+    }
+
+    /// Opens the device over already established transport, verifying its serial number.
+    pub async fn with_transport(
+        transport: Box<dyn Transport>,
+        id: &str,
+    ) -> Result<Self, OpenError> {
+        Self::with_codec(transport, LineCodec::default(), id).await
+    }
+
+    /// Same as [`Mx100qp::with_transport`], framing lines with the given codec.
+    pub async fn with_codec(
+        transport: Box<dyn Transport>,
+        codec: LineCodec,
+        id: &str,
+    ) -> Result<Self, OpenError> {
+        let (protocol, identity) = handshake(transport, codec, HANDSHAKE_TIMEOUT).await?;
+
+        if identity.serial_number != id {
+            error!("Id does not match! {} != {}", identity.serial_number, id);
             tokio::time::sleep(Duration::from_millis(1000)).await;
             return Err(OpenError::IdNotMatch(NotMatchingId {
-                configured: config.id.clone(),
-                device: device_id.to_string(),
+                configured: id.to_string(),
+                device: identity.serial_number,
             }));
         }
 
+        let model =
+            models::find(&identity.model).ok_or(OpenError::UnsupportedModel(identity.model))?;
+        info!("Detected {} {id}", model.name);
+
         Ok(Mx100qp {
             protocol,
-            status: [LimitEventStatus::empty(); 4],
+            id: id.to_string(),
+            model,
+            timeout: QUERY_TIMEOUT,
+            pipeline_depth: PIPELINE_DEPTH,
+            status: vec![LimitEventStatus::empty(); model.channels as usize],
+            settings: None,
+            pending: 0,
         })
     }
 
-    pub async fn set_voltage(&mut self, ch: u8, new_voltage: f32) -> Result<(), std::io::Error> {
-        self.protocol
-            .send(format!("V{} {new_voltage}", ch + 1))
-            .await
+    /// Sets deadline for a reply to each query.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
     }
 
-    pub async fn set_current(&mut self, ch: u8, new_current: f32) -> Result<(), std::io::Error> {
-        self.protocol
-            .send(format!("I{} {new_current}", ch + 1))
-            .await
+    /// Sets number of queries sent before waiting for their replies.
+    pub fn set_pipeline_depth(&mut self, depth: usize) {
+        self.pipeline_depth = depth;
+    }
+
+    /// Drains stale lines and verifies the device identity, so that following replies
+    /// are attributed to the right queries again.
+    ///
+    /// Replies still expected stay pending until the `*IDN?` reply is read, so that
+    /// a resync interrupted by dropping its future is not mistaken for synchronized.
+    pub async fn resync(&mut self) -> Result<(), ProtocolError> {
+        while let Ok(line) = tokio::time::timeout(RESYNC_QUIET_PERIOD, self.protocol.next()).await {
+            match line {
+                Some(line) => warn!("Discarding stale line {:?}", line?),
+                None => {
+                    return Err(ProtocolError::StreamClosed {
+                        query: "*IDN?".to_string(),
+                    });
+                }
+            }
+        }
+
+        // stale replies are gone, only the one to `*IDN?` is expected from now on
+        self.pending = 1;
+        self.protocol.send("*IDN?".to_string()).await?;
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        loop {
+            let reply = match tokio::time::timeout_at(deadline, self.protocol.next()).await {
+                Ok(Some(reply)) => reply?,
+                Ok(None) => {
+                    return Err(ProtocolError::StreamClosed {
+                        query: "*IDN?".to_string(),
+                    });
+                }
+                Err(_) => return Err(ProtocolError::NotResponding),
+            };
+
+            match Identity::parse(&reply) {
+                Some(identity) if identity.serial_number == self.id => {
+                    self.pending = 0;
+                    info!("Resynchronized with {}", self.id);
+                    return Ok(());
+                }
+                Some(_) => {
+                    self.pending = 0;
+                    return Err(ProtocolError::UnexpectedReply {
+                        query: "*IDN?".to_string(),
+                        reply,
+                    });
+                }
+                None => warn!("Discarding stale line {reply:?}"),
+            }
+        }
+    }
+
+    /// Connects to the device and returns its identity reported by `*IDN?`.
+    pub async fn identify(connection: &Connection) -> Result<Identity, OpenError> {
+        let transport = transport::open(connection).await?;
+        let (_, identity) = handshake(transport, LineCodec::default(), HANDSHAKE_TIMEOUT).await?;
+        Ok(identity)
+    }
+
+    /// Fails with [`ProtocolError::Unsupported`] if the model lacks the features.
+    fn require(&self, features: Features, command: &str) -> Result<(), ProtocolError> {
+        if self.model.supports(features) {
+            Ok(())
+        } else {
+            Err(ProtocolError::Unsupported {
+                command: command.to_string(),
+            })
+        }
+    }
+
+    /// Sends command and checks that the device accepted it.
+    ///
+    /// The command is written right away, even before replies of an interrupted poll.
+    async fn send(&mut self, command: String) -> Result<(), ProtocolError> {
+        self.settings = None;
+        self.protocol.send(command.clone()).await?;
+        self.drain().await?;
+        self.check_errors(command).await
+    }
+
+    /// Reads and clears the error registers, reporting the command that caused an error.
+    async fn check_errors(&mut self, command: String) -> Result<(), ProtocolError> {
+        let Some(error) = self.read_error_registers().await?.error() else {
+            return Ok(());
+        };
+
+        warn!("Command {command} rejected: {error}");
+        Err(ProtocolError::Rejected { command, error })
+    }
+
+    /// Reads and clears `*ESR?`, and `EER?` or `QER?` if the status reports their error.
+    async fn read_error_registers(&mut self) -> Result<ErrorRegisters, ProtocolError> {
+        let mut registers = ErrorRegisters {
+            status: self.query_parse("*ESR?".to_string()).await?,
+            ..Default::default()
+        };
+        let status = EventStatus::from_bits_truncate(registers.status);
+        if status.contains(EventStatus::EXECUTION_ERROR) {
+            registers.execution = self.query_parse("EER?".to_string()).await?;
+        }
+        if status.contains(EventStatus::QUERY_ERROR) {
+            registers.query = self.query_parse("QER?".to_string()).await?;
+        }
+        Ok(registers)
+    }
+
+    pub async fn status_byte(&mut self) -> Result<StatusByte, ProtocolError> {
+        Ok(StatusByte::from_bits_truncate(
+            self.query_parse("*STB?".to_string()).await?,
+        ))
+    }
+
+    async fn query(&mut self, query: String) -> Result<String, ProtocolError> {
+        let mut replies = self.query_batch(vec![query]).await?;
+        Ok(replies.pop().map(|(_, reply)| reply).unwrap_or_default())
+    }
+
+    /// Reads and discards replies to queries of an interrupted poll.
+    async fn drain(&mut self) -> Result<(), ProtocolError> {
+        while self.pending > 0 {
+            match tokio::time::timeout(self.timeout, self.protocol.next()).await {
+                Ok(Some(reply)) => {
+                    self.pending -= 1;
+                    debug!("Discarding reply {:?} of interrupted poll", reply?);
+                }
+                Ok(None) => {
+                    return Err(ProtocolError::StreamClosed {
+                        query: "interrupted poll".to_string(),
+                    });
+                }
+                Err(_) => {
+                    warn!("Replies of interrupted poll missing, resynchronizing");
+                    self.resync().await?;
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub async fn channel_on(&mut self, ch: u8) -> Result<(), std::io::Error> {
-        self.protocol.send(format!("OP{} 1", ch + 1)).await
+    async fn query_parse<T: FromStr>(&mut self, query: String) -> Result<T, ProtocolError> {
+        let reply = self.query(query.clone()).await?;
+        parse_reply(&query, &reply, &reply)
     }
 
-    pub async fn channel_off(&mut self, ch: u8) -> Result<(), std::io::Error> {
-        self.protocol.send(format!("OP{} 0", ch + 1)).await
+    pub async fn set_voltage(&mut self, ch: u8, new_voltage: f32) -> Result<(), ProtocolError> {
+        self.send(format!("V{} {new_voltage}", ch + 1)).await
     }
 
-    pub async fn all_channel_on(&mut self) -> Result<(), std::io::Error> {
-        self.protocol.send("OPALL 1".to_string()).await
+    pub async fn set_current(&mut self, ch: u8, new_current: f32) -> Result<(), ProtocolError> {
+        self.send(format!("I{} {new_current}", ch + 1)).await
+    }
+
+    pub async fn channel_on(&mut self, ch: u8) -> Result<(), ProtocolError> {
+        self.send(format!("OP{} 1", ch + 1)).await
+    }
+
+    pub async fn channel_off(&mut self, ch: u8) -> Result<(), ProtocolError> {
+        self.send(format!("OP{} 0", ch + 1)).await
+    }
+
+    /// Switches all outputs on, one by one if the model has no `OPALL`.
+    pub async fn all_channel_on(&mut self) -> Result<(), ProtocolError> {
+        if self.model.supports(Features::ALL_OUTPUTS) {
+            return self.send("OPALL 1".to_string()).await;
+        }
+        for ch in 0..self.model.channels {
+            self.channel_on(ch).await?;
+        }
+        Ok(())
     }
 
-    pub async fn all_channel_off(&mut self) -> Result<(), std::io::Error> {
-        self.protocol.send("OPALL 0".to_string()).await
+    /// Switches all outputs off, one by one if the model has no `OPALL`.
+    pub async fn all_channel_off(&mut self) -> Result<(), ProtocolError> {
+        if self.model.supports(Features::ALL_OUTPUTS) {
+            return self.send("OPALL 0".to_string()).await;
+        }
+        for ch in 0..self.model.channels {
+            self.channel_off(ch).await?;
+        }
+        Ok(())
     }
 
     pub async fn multichannel_on_setup(
         &mut self,
         ch: u8,
         behaviour: MultiChannelOn,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), ProtocolError> {
         let action = match behaviour {
             MultiChannelOn::Disabled => "NEVER",
             MultiChannelOn::Delay(0) => "QUICK",
             MultiChannelOn::Delay(_) => "DELAY",
         };
 
-        self.protocol
-            .send(format!("ONACTION{} {action}", ch + 1))
-            .await?;
+        self.require(Features::MULTI_ON, "ONACTION")?;
+        self.send(format!("ONACTION{} {action}", ch + 1)).await?;
 
         if let MultiChannelOn::Delay(delay) = behaviour {
-            self.protocol
-                .send(format!("ONDELAY{} {delay}", ch + 1))
-                .await?;
+            self.send(format!("ONDELAY{} {delay}", ch + 1)).await?;
         }
 
         Ok(())
     }
 
-    pub async fn set_vrange(&mut self, ch: u8, vrange: u8) -> Result<(), std::io::Error> {
-        self.protocol
-            .send(format!("VRANGE{} {vrange}", ch + 1))
-            .await
+    pub async fn multichannel_off_setup(
+        &mut self,
+        ch: u8,
+        behaviour: MultiChannelOff,
+    ) -> Result<(), ProtocolError> {
+        let action = match behaviour {
+            MultiChannelOff::Disabled => "NEVER",
+            MultiChannelOff::Delay(0) => "QUICK",
+            MultiChannelOff::Delay(_) => "DELAY",
+        };
+
+        self.require(Features::MULTI_OFF, "OFFACTION")?;
+        self.send(format!("OFFACTION{} {action}", ch + 1)).await?;
+
+        if let MultiChannelOff::Delay(delay) = behaviour {
+            self.send(format!("OFFDELAY{} {delay}", ch + 1)).await?;
+        }
+
+        Ok(())
     }
 
-    pub async fn set_voltage_tracking(
-        &mut self,
-        config: VoltageTracking,
-    ) -> Result<(), std::io::Error> {
-        self.protocol
-            .send(format!("CONFIG {}", u8::from(config)))
-            .await
+    pub async fn set_vrange(&mut self, ch: u8, vrange: u8) -> Result<(), ProtocolError> {
+        match self.model.range_command {
+            Some(command) if self.model.has_ranges(ch) => {
+                self.send(format!("{command}{} {vrange}", ch + 1)).await
+            }
+            _ => Err(ProtocolError::Unsupported {
+                command: format!("range of channel {}", ch + 1),
+            }),
+        }
     }
 
-    pub async fn get_voltage_tracking(&mut self) -> Result<VoltageTracking, std::io::Error> {
-        self.protocol.send("CONFIG?".to_string()).await?;
-        Ok(self
-            .protocol
-            .next()
-            .await
-            .unwrap()?
-            .parse::<u8>()
-            .unwrap()
-            .try_into()
-            .unwrap())
+    /// Selects the voltage tracking mode by its `CONFIG` value.
+    pub async fn set_voltage_tracking(&mut self, config: u8) -> Result<(), ProtocolError> {
+        let command = format!("CONFIG {config}");
+        if self.model.tracking_mode(config).is_none() {
+            return Err(ProtocolError::Unsupported { command });
+        }
+        self.send(command).await
+    }
+
+    /// Reads the voltage tracking mode `CONFIG` value.
+    pub async fn get_voltage_tracking(&mut self) -> Result<u8, ProtocolError> {
+        if self.model.tracking.is_empty() {
+            return Ok(self.model.default_tracking());
+        }
+
+        let query = "CONFIG?".to_string();
+        let reply = self.query(query.clone()).await?;
+        let config: u8 = parse_reply(&query, &reply, &reply)?;
+        match self.model.tracking_mode(config) {
+            Some(_) => Ok(config),
+            None => Err(ProtocolError::UnexpectedReply { query, reply }),
+        }
     }
 
     pub async fn set_overvoltage_trip(
         &mut self,
         ch: u8,
         voltage: Option<f32>,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), ProtocolError> {
         self.set_trip(ch, voltage, 'V').await
     }
 
@@ -270,7 +685,7 @@ impl Mx100qp {
         &mut self,
         ch: u8,
         current: Option<f32>,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), ProtocolError> {
         self.set_trip(ch, current, 'C').await
     }
 
@@ -279,119 +694,329 @@ impl Mx100qp {
         ch: u8,
         threshold: Option<f32>,
         unit: char,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), ProtocolError> {
         if let Some(threshold) = threshold {
-            self.protocol
-                .send(format!("O{unit}P{} {threshold}", ch + 1))
-                .await?;
+            self.send(format!("O{unit}P{} {threshold}", ch + 1)).await?;
         }
 
         let action = match threshold {
             None => "OFF",
             Some(_) => "ON",
         };
-        self.protocol
-            .send(format!("O{unit}P{} {action}", ch + 1))
-            .await
+        self.send(format!("O{unit}P{} {action}", ch + 1)).await
     }
 
-    pub async fn trip_reset(&mut self) -> Result<(), std::io::Error> {
-        self.protocol.send("TRIPRST".to_string()).await?;
-        self.status = [LimitEventStatus::empty(); 4];
+    pub async fn trip_reset(&mut self) -> Result<(), ProtocolError> {
+        self.send("TRIPRST".to_string()).await?;
+        self.status.fill(LimitEventStatus::empty());
         Ok(())
     }
 
-    pub async fn read_channels(&mut self) -> Result<Vec<Channel>, std::io::Error> {
-        let mut mychannels = Vec::<Channel>::new();
+    /// Saves setup of all channels to the store.
+    pub async fn save_setup(&mut self, store: u8) -> Result<(), ProtocolError> {
+        self.require(Features::SETUP_STORES, "*SAV")?;
+        self.send(format!("*SAV {store}")).await
+    }
 
-        let voltage_tracking = self.get_voltage_tracking().await?;
+    /// Recalls setup of all channels from the store.
+    pub async fn recall_setup(&mut self, store: u8) -> Result<(), ProtocolError> {
+        self.require(Features::SETUP_STORES, "*RCL")?;
+        self.send(format!("*RCL {store}")).await
+    }
 
-        for i in 1..=4u8 {
-            // read status
-            self.protocol.send(format!("LSR{i}?")).await?;
-            let status = LimitEventStatus::from_bits(
-                self.protocol.next().await.unwrap()?.parse::<u8>().unwrap(),
-            )
-            .unwrap();
+    /// Saves setup of the channel to the store.
+    pub async fn save_channel_setup(&mut self, ch: u8, store: u8) -> Result<(), ProtocolError> {
+        self.require(Features::CHANNEL_SETUP_STORES, "SAV")?;
+        self.send(format!("SAV{} {store}", ch + 1)).await
+    }
 
-            self.status[(i - 1) as usize] |= status;
-            let status = self.status[(i - 1) as usize];
-
-            self.protocol.send(format!("OP{i}?")).await?;
-            let enabled = self.protocol.next().await.unwrap()? == "1";
-
-            self.protocol.send(format!("VRANGE{i}?")).await?;
-            let vrange = self.protocol.next().await.unwrap()?.parse().unwrap();
-
-            // XXX: power supply is not responding OVP2? if CONFIG == 3
-            let ovp = if voltage_tracking == VoltageTracking::CH0_1AndCH2_3 && i == 2 {
-                None
-            } else {
-                self.protocol.send(format!("OVP{i}?")).await?;
-                match self
-                    .protocol
-                    .next()
-                    .await
-                    .unwrap()?
-                    .split_once(' ')
-                    .unwrap()
-                    .1
-                {
-                    "OFF" => None,
-                    val => Some(val.parse().unwrap()),
+    /// Recalls setup of the channel from the store.
+    pub async fn recall_channel_setup(&mut self, ch: u8, store: u8) -> Result<(), ProtocolError> {
+        self.require(Features::CHANNEL_SETUP_STORES, "RCL")?;
+        self.send(format!("RCL{} {store}", ch + 1)).await
+    }
+
+    /// Switches all outputs off and applies tracking and all channel settings of the configuration.
+    pub async fn apply_config(&mut self, config: &PowerSupplyConfig) -> Result<(), ProtocolError> {
+        self.all_channel_off().await?;
+
+        if !self.model.tracking.is_empty() {
+            self.set_voltage_tracking(config.voltage_tracking).await?;
+        }
+
+        let channels = config.channels.iter().take(self.model.channels as usize);
+        for (ch, channel_config) in channels.enumerate() {
+            let ch = ch as u8;
+            if self.model.has_ranges(ch) {
+                self.set_vrange(ch, channel_config.vrange).await?;
+            }
+            self.set_voltage(ch, channel_config.voltage).await?;
+            self.set_current(ch, channel_config.current).await?;
+            self.set_overvoltage_trip(ch, channel_config.overvoltage_trip)
+                .await?;
+            self.set_overcurrent_trip(ch, channel_config.overcurrent_trip)
+                .await?;
+            if self.model.supports(Features::MULTI_ON) {
+                self.multichannel_on_setup(
+                    ch,
+                    match channel_config.multi_on.enabled {
+                        true => MultiChannelOn::Delay(channel_config.multi_on.delay_ms),
+                        false => MultiChannelOn::Disabled,
+                    },
+                )
+                .await?;
+            }
+            if self.model.supports(Features::MULTI_OFF) {
+                self.multichannel_off_setup(
+                    ch,
+                    match channel_config.multi_off.enabled {
+                        true => MultiChannelOff::Delay(channel_config.multi_off.delay_ms),
+                        false => MultiChannelOff::Disabled,
+                    },
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a raw line from another program, returning the replies to its queries joined by
+    /// `;`, or `None` if the line has no query.
+    ///
+    /// Commands and queries separated by `;` are sent one by one. Errors they cause are moved
+    /// into the registers of the client, which answer its `*ESR?`, `EER?` and `QER?` and are
+    /// cleared by its `*CLS`, so that they are not mistaken for errors of the GUI's commands
+    /// and the client does not clear the GUI's errors. A line with queries causing an error
+    /// fails with [`ProtocolError::Rejected`]. Cached settings are read again.
+    pub async fn passthrough(
+        &mut self,
+        line: &str,
+        errors: &mut ErrorRegisters,
+    ) -> Result<Option<String>, ProtocolError> {
+        self.settings = None;
+        let result = self.passthrough_parts(line, errors).await;
+        if let Err(err) = &result
+            && !err.is_recoverable()
+        {
+            return result;
+        }
+
+        let registers = self.read_error_registers().await?;
+        errors.merge(registers);
+        let replies = result?;
+        match registers.error() {
+            Some(error) if replies.is_some() => {
+                warn!("Passthrough {line} rejected: {error}");
+                Err(ProtocolError::Rejected {
+                    command: line.to_string(),
+                    error,
+                })
+            }
+            _ => Ok(replies),
+        }
+    }
+
+    async fn passthrough_parts(
+        &mut self,
+        line: &str,
+        errors: &mut ErrorRegisters,
+    ) -> Result<Option<String>, ProtocolError> {
+        let mut replies: Option<Vec<String>> = None;
+        for part in line
+            .split(';')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let header = part.to_ascii_uppercase();
+            if matches!(header.as_str(), "*ESR?" | "EER?" | "QER?" | "*CLS") {
+                // errors of the previous parts of the line are read first
+                let registers = self.read_error_registers().await?;
+                errors.merge(registers);
+            }
+
+            let register = match header.as_str() {
+                "*ESR?" => &mut errors.status,
+                "EER?" => &mut errors.execution,
+                "QER?" => &mut errors.query,
+                "*CLS" => {
+                    *errors = ErrorRegisters::default();
+                    continue;
+                }
+                _ if part.contains('?') => {
+                    let reply = self.query(part.to_string()).await?;
+                    replies.get_or_insert_default().push(reply);
+                    continue;
+                }
+                _ => {
+                    self.protocol.send(part.to_string()).await?;
+                    self.drain().await?;
+                    continue;
                 }
             };
+            let value = std::mem::take(register);
+            replies.get_or_insert_default().push(value.to_string());
+        }
+        Ok(replies.map(|replies| replies.join(";")))
+    }
 
-            let ocp = if voltage_tracking == VoltageTracking::CH0_1AndCH2_3 && i == 2 {
-                None
-            } else {
-                self.protocol.send(format!("OCP{i}?")).await?;
-                match self
-                    .protocol
-                    .next()
-                    .await
-                    .unwrap()?
-                    .split_once(' ')
-                    .unwrap()
-                    .1
-                {
-                    "OFF" => None,
-                    val => Some(val.parse().unwrap()),
+    /// Sends the queries in batches of the pipeline depth, reading the replies after each
+    /// batch, so that a batch costs a single round trip.
+    async fn query_batch(
+        &mut self,
+        queries: Vec<String>,
+    ) -> Result<Vec<(String, String)>, ProtocolError> {
+        self.drain().await?;
+
+        let mut replies = Vec::with_capacity(queries.len());
+        for batch in queries.chunks(self.pipeline_depth.max(1)) {
+            for query in batch {
+                self.protocol.feed(query.clone()).await?;
+                self.pending += 1;
+            }
+            self.protocol.flush().await?;
+
+            for query in batch {
+                match tokio::time::timeout(self.timeout, self.protocol.next()).await {
+                    Ok(Some(reply)) => {
+                        self.pending -= 1;
+                        replies.push((query.clone(), reply?));
+                    }
+                    Ok(None) => {
+                        return Err(ProtocolError::StreamClosed {
+                            query: query.clone(),
+                        });
+                    }
+                    Err(_) => {
+                        warn!("No reply to {query}, resynchronizing");
+                        self.resync().await?;
+                        return Err(ProtocolError::Timeout {
+                            query: query.clone(),
+                        });
+                    }
                 }
-            };
+            }
+        }
+        Ok(replies)
+    }
 
-            mychannels.push(Channel {
-                enabled,
-                vrange,
-                index: i - 1,
-                overvoltage_trip: ovp,
-                overcurrent_trip: ocp,
-                status,
-                voltage: read_unit(&mut self.protocol, i, 'V').await?,
-                current: read_unit(&mut self.protocol, i, 'I').await?,
-                voltage_tracking: VoltageTrackingState::from_channel_and_config(
-                    i - 1,
-                    &voltage_tracking,
+    /// Reads rarely changing settings: tracking, ranges, trips and setpoints.
+    async fn read_settings(&mut self) -> Result<Settings, ProtocolError> {
+        let voltage_tracking = self.get_voltage_tracking().await?;
+
+        let mut queries = Vec::new();
+        for i in 1..=self.model.channels {
+            if let Some(command) = self.model.range_command
+                && self.model.has_ranges(i - 1)
+            {
+                queries.push(format!("{command}{i}?"));
+            }
+            if !self.skips_trips(voltage_tracking, i) {
+                queries.push(format!("OVP{i}?"));
+                queries.push(format!("OCP{i}?"));
+            }
+            queries.push(format!("V{i}?"));
+            queries.push(format!("I{i}?"));
+        }
+
+        let mut replies = self.query_batch(queries).await?.into_iter();
+        let mut channels = Vec::new();
+        for i in 1..=self.model.channels {
+            let vrange = match self.model.has_ranges(i - 1) {
+                true => parse_whole_reply(next_reply(&mut replies))?,
+                false => 0,
+            };
+            let (overvoltage_trip, overcurrent_trip) = match self.skips_trips(voltage_tracking, i) {
+                true => (None, None),
+                false => (
+                    parse_trip(next_reply(&mut replies))?,
+                    parse_trip(next_reply(&mut replies))?,
                 ),
+            };
+            channels.push(ChannelSettings {
+                vrange,
+                overvoltage_trip,
+                overcurrent_trip,
+                voltage: parse_setpoint(next_reply(&mut replies), format!("V{i}"))?,
+                current: parse_setpoint(next_reply(&mut replies), format!("I{i}"))?,
             });
         }
-        Ok(mychannels)
+
+        Ok(Settings {
+            voltage_tracking,
+            channels,
+        })
     }
-}
 
-fn find_usb(config: &PowerSupplyConfig) -> Option<String> {
-    let ports: Vec<serialport::SerialPortInfo> =
-        serialport::available_ports().expect("No ports found!");
-    ports.iter().find_map(|p| {
-        if let serialport::SerialPortType::UsbPort(usbinfo) = &p.port_type
-            && config.pid == usbinfo.pid
-            && config.vid == usbinfo.vid
-            && config.serial_number == usbinfo.serial_number
-        {
-            return Some(p.port_name.clone());
+    fn skips_trips(&self, voltage_tracking: u8, ch: u8) -> bool {
+        self.model.has_silent_trips(voltage_tracking, ch)
+    }
+
+    /// Reads all channels including their settings.
+    pub async fn read_channels(&mut self) -> Result<Vec<Channel>, ProtocolError> {
+        self.poll(true).await
+    }
+
+    /// Reads fast changing output state and readbacks of all channels. Settings are read
+    /// as well if requested, or if they may have been changed by a command since the last read.
+    ///
+    /// The poll can be interrupted at any await point by dropping it, replies still in flight
+    /// are discarded before the next query.
+    pub async fn poll(&mut self, read_settings: bool) -> Result<Vec<Channel>, ProtocolError> {
+        let settings = match self.settings.take() {
+            Some(settings) if !read_settings => settings,
+            _ => self.read_settings().await?,
+        };
+        let tracking_mode = self.model.tracking_mode(settings.voltage_tracking);
+
+        let mut queries = Vec::new();
+        for i in 1..=self.model.channels {
+            queries.push(format!("LSR{i}?"));
+            queries.push(format!("OP{i}?"));
+            queries.push(format!("V{i}O?"));
+            queries.push(format!("I{i}O?"));
         }
-        None
-    })
+        let mut replies = self.query_batch(queries).await?.into_iter();
+
+        let mut channels = Vec::new();
+        for (index, channel) in settings.channels.iter().enumerate() {
+            let (query, reply) = next_reply(&mut replies);
+            let status = LimitEventStatus::from_bits(parse_reply(&query, &reply, &reply)?)
+                .ok_or(ProtocolError::UnexpectedReply { query, reply })?;
+            self.status[index] |= status;
+
+            let enabled = match next_reply(&mut replies) {
+                (_, reply) if reply == "1" => true,
+                (_, reply) if reply == "0" => false,
+                (query, reply) => return Err(ProtocolError::UnexpectedReply { query, reply }),
+            };
+
+            channels.push(Channel {
+                index: index as u8,
+                vrange: channel.vrange,
+                enabled,
+                voltage: Unit {
+                    current: parse_readback(next_reply(&mut replies))?,
+                    set: channel.voltage,
+                },
+                current: Unit {
+                    current: parse_readback(next_reply(&mut replies))?,
+                    set: channel.current,
+                },
+                overvoltage_trip: channel.overvoltage_trip,
+                overcurrent_trip: channel.overcurrent_trip,
+                status: self.status[index],
+                voltage_tracking: VoltageTrackingState::from_channel_and_mode(
+                    index as u8,
+                    tracking_mode,
+                ),
+            });
+        }
+
+        self.settings = Some(settings);
+        self.protocol.codec_mut().flush_transcript();
+        Ok(channels)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -408,21 +1033,14 @@ pub enum VoltageTrackingState {
 }
 
 impl VoltageTrackingState {
-    fn from_channel_and_config(ch: u8, config: &VoltageTracking) -> VoltageTrackingState {
-        match (config, ch) {
-            (VoltageTracking::CH0_1, 0) | (VoltageTracking::CH0_1AndCH2_3, 0) => {
-                VoltageTrackingState::Master
-            }
-            (VoltageTracking::CH0_1, 1) | (VoltageTracking::CH0_1AndCH2_3, 1) => {
-                VoltageTrackingState::Slave
-            }
-            (VoltageTracking::CH2_3, 2) | (VoltageTracking::CH0_1AndCH2_3, 2) => {
-                VoltageTrackingState::Master
-            }
-            (VoltageTracking::CH2_3, 3) | (VoltageTracking::CH0_1AndCH2_3, 3) => {
-                VoltageTrackingState::Slave
-            }
-            _ => VoltageTrackingState::None,
+    fn from_channel_and_mode(ch: u8, mode: Option<&TrackingMode>) -> VoltageTrackingState {
+        let pairs = mode.map(|mode| mode.pairs).unwrap_or_default();
+        if pairs.iter().any(|(master, _)| *master == ch) {
+            VoltageTrackingState::Master
+        } else if pairs.iter().any(|(_, slave)| *slave == ch) {
+            VoltageTrackingState::Slave
+        } else {
+            VoltageTrackingState::None
         }
     }
 }
@@ -440,30 +1058,130 @@ pub struct Channel {
     pub voltage_tracking: VoltageTrackingState,
 }
 
-async fn read_unit(
-    reader: &mut Framed<SerialStream, LineCodec>,
-    channel: u8,
-    unit: char,
-) -> Result<Unit, std::io::Error> {
-    reader.send(format!("{unit}{channel}O?")).await?;
-    let mut current = reader.next().await.unwrap()?;
-    current.truncate(current.len() - 1);
-
-    reader.send(format!("{unit}{channel}?")).await?;
-    let set = reader.next().await.unwrap()?;
-    let mut parts = set.split_terminator(' ');
-    assert!((parts.next()).unwrap() == format!("{unit}{channel}"));
-    let set = parts.next().unwrap();
-
-    Ok(Unit {
-        current: current.parse().unwrap(),
-        set: set.parse().unwrap(),
+fn parse_reply<T: FromStr>(query: &str, reply: &str, value: &str) -> Result<T, ProtocolError> {
+    value.trim().parse().map_err(|_| ProtocolError::Parse {
+        query: query.to_string(),
+        reply: reply.to_string(),
     })
 }
 
-async fn wait_first_line(
-    protocol: &mut Framed<SerialStream, LineCodec>,
-) -> Result<std::string::String, std::io::Error> {
+/// Takes the next reply of a batch, the batch always has a reply for each query.
+fn next_reply(replies: &mut impl Iterator<Item = (String, String)>) -> (String, String) {
+    replies.next().unwrap_or_default()
+}
+
+fn parse_whole_reply<T: FromStr>((query, reply): (String, String)) -> Result<T, ProtocolError> {
+    parse_reply(&query, &reply, &reply)
+}
+
+/// Parses trip threshold such as `VP1 10.000` or `VP1 OFF`.
+fn parse_trip((query, reply): (String, String)) -> Result<Option<f32>, ProtocolError> {
+    match reply.split_once(' ') {
+        Some((_, "OFF")) => Ok(None),
+        Some((_, value)) => Ok(Some(parse_reply(&query, &reply, value)?)),
+        None => Err(ProtocolError::UnexpectedReply { query, reply }),
+    }
+}
+
+/// Parses setpoint such as `V1 5.000`.
+fn parse_setpoint((query, reply): (String, String), header: String) -> Result<f32, ProtocolError> {
+    match reply.split_once(' ') {
+        Some((prefix, value)) if prefix == header => parse_reply(&query, &reply, value),
+        _ => Err(ProtocolError::UnexpectedReply { query, reply }),
+    }
+}
+
+/// Parses output readback such as `5.000V`.
+fn parse_readback((query, reply): (String, String)) -> Result<f32, ProtocolError> {
+    match reply.strip_suffix(['V', 'A']) {
+        Some(value) => parse_reply(&query, &reply, value),
+        None => Err(ProtocolError::UnexpectedReply { query, reply }),
+    }
+}
+
+/// Finds the serial port of the device with the serial number among ports matching the USB
+/// identification, returning the error of the last candidate if none matches.
+async fn probe_usb(vid: u16, pid: u16, id: &str) -> Result<String, OpenError> {
+    let mut result = Err(OpenError::NoDeviceFound);
+    for port_path in transport::find_usb(vid, pid, None) {
+        let transport = match transport::open_serial(&port_path) {
+            Ok(transport) => transport,
+            Err(err) => {
+                debug!("Skipping {port_path}: {err}");
+                continue;
+            }
+        };
+        // the port is reopened with the configured codec, so the probe is not recorded
+        match handshake(transport, LineCodec::default(), HANDSHAKE_TIMEOUT).await {
+            Ok((_, identity)) if identity.serial_number == id => return Ok(port_path),
+            Ok((_, identity)) => {
+                debug!("Skipping {port_path}: device id {}", identity.serial_number);
+                result = Err(OpenError::IdNotMatch(NotMatchingId {
+                    configured: id.to_string(),
+                    device: identity.serial_number,
+                }));
+            }
+            Err(err) => {
+                debug!("Skipping {port_path}: {err}");
+                result = Err(err);
+            }
+        }
+    }
+    result
+}
+
+/// Codec recording a transcript if configured.
+fn codec(config: &PowerSupplyConfig) -> Result<LineCodec, OpenError> {
+    match &config.transcript {
+        Some(path) => {
+            let header = format!("{} {} {}", config.id, config.connection, config.name);
+            Ok(LineCodec::recording(TranscriptWriter::create(
+                path, &header,
+            )?))
+        }
+        None => Ok(LineCodec::default()),
+    }
+}
+
+/// Identification of the device reported by `*IDN?`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub manufacturer: String,
+    pub model: String,
+    pub serial_number: String,
+    pub firmware: String,
+}
+
+impl Identity {
+    pub fn parse(reply: &str) -> Option<Identity> {
+        let mut fields = reply.split_terminator(',').map(str::trim);
+        Some(Identity {
+            manufacturer: fields.next()?.to_string(),
+            model: fields.next()?.to_string(),
+            serial_number: fields.next()?.to_string(),
+            firmware: fields.next().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// Repeats `*IDN?` until the device answers or the timeout elapses.
+pub(crate) async fn handshake(
+    transport: Box<dyn Transport>,
+    codec: LineCodec,
+    timeout: Duration,
+) -> Result<(Protocol, Identity), OpenError> {
+    let mut protocol = codec.framed(transport);
+
+    let line_result = tokio::time::timeout(timeout, wait_first_line(&mut protocol)).await??;
+    let identity = Identity::parse(&line_result).ok_or_else(|| ProtocolError::UnexpectedReply {
+        query: "*IDN?".to_string(),
+        reply: line_result.clone(),
+    })?;
+
+    Ok((protocol, identity))
+}
+
+async fn wait_first_line(protocol: &mut Protocol) -> Result<std::string::String, std::io::Error> {
     loop {
         protocol.send("*IDN?".to_string()).await?;
         match tokio::time::timeout(Duration::from_millis(100), protocol.next()).await {
@@ -479,15 +1197,273 @@ async fn wait_first_line(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::simulator::Simulator;
+
+    #[tokio::test]
+    async fn test_unexpected_reply() {
+        let (client, server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut server = LineCodec::default().framed(server);
+            while let Some(Ok(line)) = server.next().await {
+                let reply = match line.as_str() {
+                    "*IDN?" => "THURLBY THANDAR, MX100QP, 123, 1.00",
+                    _ => "Unknown",
+                };
+                server.send(reply.to_string()).await.unwrap();
+            }
+        });
+
+        let mut port = Mx100qp::with_transport(Box::new(client), "123")
+            .await
+            .unwrap();
+        assert!(matches!(
+            port.read_channels().await,
+            Err(ProtocolError::Parse { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_model() {
+        let (client, server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut server = LineCodec::default().framed(server);
+            while let Some(Ok(_)) = server.next().await {
+                let reply = "THURLBY THANDAR, MX100TP, 123, 1.00-1.00-1.00";
+                server.send(reply.to_string()).await.unwrap();
+            }
+        });
+
+        assert!(matches!(
+            Mx100qp::with_transport(Box::new(client), "123").await,
+            Err(OpenError::UnsupportedModel(model)) if model == "MX100TP"
+        ));
+    }
+
+    /// Transcript buffer shared between the codec and the test.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        /// Sent lines recorded since the last call.
+        fn take_sent(&self) -> Vec<String> {
+            let recorded = std::mem::take(&mut *self.0.lock().unwrap());
+            crate::transcript::parse(&String::from_utf8_lossy(&recorded))
+                .unwrap()
+                .into_iter()
+                .filter(|(direction, _)| *direction == crate::transcript::Direction::Sent)
+                .map(|(_, line)| line)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_poll_settings() {
+        let buffer = SharedBuffer::default();
+        let codec = LineCodec::recording(TranscriptWriter::new(buffer.clone(), "poll").unwrap());
+        let mut port = Mx100qp::with_codec(
+            Box::new(Simulator::new(&models::MX100QP, "SIM")),
+            codec,
+            "SIM",
+        )
+        .await
+        .unwrap();
+        port.set_pipeline_depth(3);
+
+        port.set_voltage(0, 5.0).await.unwrap();
+        port.set_current(0, 1.0).await.unwrap();
+        port.channel_on(0).await.unwrap();
+        let channels = port.poll(false).await.unwrap();
+        assert_eq!(channels[0].voltage.set, 5.0);
+        // settings are read by the first poll after the commands
+        let sent = buffer.take_sent();
+        assert!(sent.iter().any(|line| line == "V1?"));
+        assert!(sent.iter().any(|line| line == "V1O?"));
+
+        let channels = port.poll(false).await.unwrap();
+        assert!(channels[0].enabled);
+        assert_eq!(channels[0].voltage.set, 5.0);
+        assert_eq!(channels[0].voltage.current, 5.0);
+        // but not by the next one
+        let sent = buffer.take_sent();
+        assert!(!sent.iter().any(|line| line == "V1?"));
+        assert!(sent.iter().any(|line| line == "V1O?"));
+    }
+
+    #[tokio::test]
+    async fn test_silent_trips() {
+        let mut port =
+            Mx100qp::with_transport(Box::new(Simulator::new(&models::MX100QP, "SIM")), "SIM")
+                .await
+                .unwrap();
+        port.set_overvoltage_trip(0, Some(20.0)).await.unwrap();
+        port.set_overvoltage_trip(1, Some(20.0)).await.unwrap();
+        port.set_voltage_tracking(3).await.unwrap();
+
+        // OVP2?/OCP2? are not sent, the device would not answer them
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[0].overvoltage_trip, Some(20.0));
+        assert_eq!(channels[1].overvoltage_trip, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interrupted_poll() {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let mut simulator = Simulator::new(&models::MX100QP, "SIM");
+            let mut server = LineCodec::default().framed(server);
+            while let Some(Ok(line)) = server.next().await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                if let Some(reply) = simulator.process(&line) {
+                    server.send(reply).await.unwrap();
+                }
+            }
+        });
+
+        let mut port = Mx100qp::with_transport(Box::new(client), "SIM")
+            .await
+            .unwrap();
+        port.set_current(0, 1.0).await.unwrap();
+        port.channel_on(0).await.unwrap();
+
+        let poll = tokio::time::timeout(Duration::from_millis(25), port.poll(true)).await;
+        assert!(poll.is_err());
+        assert!(port.pending > 0);
+
+        port.channel_off(0).await.unwrap();
+        assert_eq!(port.pending, 0);
+        let channels = port.read_channels().await.unwrap();
+        assert!(!channels[0].enabled);
+        assert_eq!(channels[0].current.set, 1.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resync_after_missed_reply() {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let mut simulator = Simulator::new(&models::MX100QP, "SIM");
+            let mut server = LineCodec::default().framed(server);
+            let mut missed = None;
+            let mut delivered = false;
+            while let Some(Ok(line)) = server.next().await {
+                let reply = simulator.process(&line);
+                if line == "OP1?" && missed.is_none() && !delivered {
+                    missed = reply;
+                    continue;
+                }
+                // deliver the missed reply late, just before answering the resync
+                if line == "*IDN?"
+                    && let Some(late) = missed.take()
+                {
+                    delivered = true;
+                    server.send(late).await.unwrap();
+                }
+                if let Some(reply) = reply {
+                    server.send(reply).await.unwrap();
+                }
+            }
+        });
+
+        let mut port = Mx100qp::with_transport(Box::new(client), "SIM")
+            .await
+            .unwrap();
+        let err = port.read_channels().await.unwrap_err();
+        assert!(matches!(err, ProtocolError::Timeout { .. }));
+        assert!(err.is_recoverable());
+
+        port.set_voltage(0, 3.3).await.unwrap();
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[0].voltage.set, 3.3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interrupted_resync() {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let mut simulator = Simulator::new(&models::MX100QP, "SIM");
+            let mut server = LineCodec::default().framed(server);
+            let mut handshake = true;
+            while let Some(Ok(line)) = server.next().await {
+                // the identity is answered slowly after the handshake
+                if line == "*IDN?" && !std::mem::take(&mut handshake) {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+                if let Some(reply) = simulator.process(&line) {
+                    server.send(reply).await.unwrap();
+                }
+            }
+        });
+
+        let mut port = Mx100qp::with_transport(Box::new(client), "SIM")
+            .await
+            .unwrap();
+        // dropped after sending `*IDN?`, before its reply arrives
+        let resync = tokio::time::timeout(Duration::from_millis(300), port.resync()).await;
+        assert!(resync.is_err());
+        assert_eq!(port.pending, 1);
+
+        port.set_voltage(0, 3.3).await.unwrap();
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[0].voltage.set, 3.3);
+    }
+
+    #[tokio::test]
+    async fn test_passthrough() {
+        let mut port =
+            Mx100qp::with_transport(Box::new(Simulator::new(&models::MX100QP, "SIM")), "SIM")
+                .await
+                .unwrap();
+        let mut errors = ErrorRegisters::default();
+        assert_eq!(port.passthrough("V2 4.2", &mut errors).await.unwrap(), None);
+        assert_eq!(
+            port.passthrough("V2?;I2 0.5;I2?", &mut errors)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("V2 4.200;I2 0.500")
+        );
+        assert_eq!(port.read_channels().await.unwrap()[1].voltage.set, 4.2);
+
+        // errors of the client go to its registers, not to the next command of the GUI
+        assert_eq!(port.passthrough("V2 999", &mut errors).await.unwrap(), None);
+        port.set_voltage(1, 5.0).await.unwrap();
+        assert_eq!(
+            port.passthrough("*ESR?;EER?;*ESR?", &mut errors)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("16;100;0")
+        );
+
+        // and errors of the GUI are not reported to the client
+        assert!(port.set_voltage(1, 999.0).await.is_err());
+        assert_eq!(
+            port.passthrough("*ESR?", &mut errors)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("0")
+        );
 
-    #[test]
-    fn test_auto_vrange() {
-        assert_eq!(auto_vrange(0, 0.0, 0.0), Some(2));
-        assert_eq!(auto_vrange(0, 1.0, 2.0), Some(2));
-        assert_eq!(auto_vrange(0, 35.0, 0.1), Some(1));
-        assert_eq!(auto_vrange(0, 35.0, 3.0), Some(1));
-        assert_eq!(auto_vrange(0, 35.0, 5.0), Some(3));
-        assert_eq!(auto_vrange(0, 35.0, 6.0), Some(3));
-        assert_eq!(auto_vrange(0, 70.0, 0.1), None);
+        let mut errors = ErrorRegisters::default();
+        assert!(matches!(
+            port.passthrough("V2 999;V2?", &mut errors).await,
+            Err(ProtocolError::Rejected {
+                error: InstrumentError::Execution(EXECUTION_ERROR_RANGE),
+                ..
+            })
+        ));
+        port.passthrough("*CLS", &mut errors).await.unwrap();
+        assert_eq!(errors, ErrorRegisters::default());
     }
 }
@@ -0,0 +1,145 @@
+//! Raw SCPI socket for other programs, sharing the connection of the GUI.
+//!
+//! Lines of all clients are queued into the coroutine of the supply, so they are
+//! serialised with polling and each client gets the replies to its own queries and
+//! its own error registers.
+
+use futures::{SinkExt, StreamExt, future::join_all};
+use log::{error, info, warn};
+use power_supply_control::{
+    config::PowerSupplyConfig, line_codec::LineCodec, mx100qp::ErrorRegisters,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+};
+use tokio_util::codec::Decoder;
+
+use crate::{components::power_supply::PowerSupplyAction, supplies::Supplies};
+
+async fn handle_client(stream: TcpStream, id: &str, supplies: Supplies) {
+    let mut lines = LineCodec::default().framed(stream);
+    let mut errors = ErrorRegisters::default();
+    while let Some(Ok(line)) = lines.next().await {
+        if line.is_empty() {
+            continue;
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let action = PowerSupplyAction::Passthrough(line.clone(), errors, tx);
+        let result = match supplies.send(id, action) {
+            Ok(()) => match rx.await {
+                Ok((updated, result)) => {
+                    errors = updated;
+                    result
+                }
+                // the coroutine is gone with the supply
+                Err(_) => Err("dropped".to_string()),
+            },
+            Err(err) => Err(err),
+        };
+
+        let reply = match result {
+            Ok(reply) => reply,
+            Err(err) => {
+                warn!("Passthrough {line:?} failed: {err}");
+                // every query is answered, so that the client does not wait forever
+                line.contains('?').then(|| format!("ERR {err}"))
+            }
+        };
+        if let Some(reply) = reply
+            && lines.send(reply).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn serve_supply(bind: String, id: String, supplies: Supplies) {
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to start passthrough of {id} on {bind}: {err}");
+            return;
+        }
+    };
+    info!("Passthrough of {id} listening on {bind}");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                info!("Passthrough client {addr} connected to {id}");
+                let id = id.clone();
+                let supplies = supplies.clone();
+                tokio::spawn(async move {
+                    handle_client(stream, &id, supplies).await;
+                    info!("Passthrough client {addr} disconnected");
+                });
+            }
+            Err(err) => warn!("Failed to accept passthrough client: {err}"),
+        }
+    }
+}
+
+/// Serves passthrough sockets of all supplies having one configured.
+pub async fn serve(power_supplies: Vec<PowerSupplyConfig>, supplies: Supplies) {
+    let servers = power_supplies.into_iter().filter_map(|config| {
+        let bind = config.passthrough?;
+        Some(serve_supply(bind, config.id, supplies.clone()))
+    });
+    join_all(servers).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::supply;
+    use futures::channel::mpsc::unbounded;
+
+    #[tokio::test]
+    async fn test_passthrough() {
+        let supplies = Supplies::default();
+        let (tx, mut rx) = unbounded();
+        supplies.register(supply(&[]), tx);
+
+        // answers like the coroutine of the supply
+        tokio::spawn(async move {
+            while let Some(action) = rx.next().await {
+                if let PowerSupplyAction::Passthrough(line, mut errors, reply) = action {
+                    let answer = match line.as_str() {
+                        "*ESR?" => Ok(Some(std::mem::take(&mut errors.status).to_string())),
+                        line if line.starts_with("BAD") => {
+                            errors.status |= 32;
+                            Err("Query timed out".to_string())
+                        }
+                        line => Ok(line.contains('?').then(|| format!("reply to {line}"))),
+                    };
+                    reply.send((errors, answer)).unwrap();
+                }
+            }
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_client(stream, "SIM", supplies).await;
+        });
+
+        let mut client = LineCodec::default().framed(TcpStream::connect(addr).await.unwrap());
+        client.send("OP1 1".to_string()).await.unwrap();
+        client.send("V1?".to_string()).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), "reply to V1?");
+
+        // failed queries are answered with an error, failed commands are not
+        client.send("BAD 1".to_string()).await.unwrap();
+        client.send("BAD?".to_string()).await.unwrap();
+        client.send("I1?".to_string()).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), "ERR Query timed out");
+        assert_eq!(client.next().await.unwrap().unwrap(), "reply to I1?");
+
+        // error registers of the client are kept between its lines
+        client.send("*ESR?".to_string()).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), "32");
+    }
+}
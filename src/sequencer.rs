@@ -0,0 +1,362 @@
+//! Software power sequencer running named sequences of steps across channels of all supplies.
+
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use power_supply_control::config::SequenceStep;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    components::power_supply::{ChannelSelection, PowerSupplyAction},
+    supplies::{ChannelState, Event, Supplies, SupplyState},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SequenceError {
+    UnknownSupply(String),
+    UnknownChannel {
+        supply: String,
+        channel: String,
+    },
+    /// The action could not be queued into the supply.
+    Send(String),
+    Timeout {
+        supply: String,
+        channel: String,
+    },
+    /// Telemetry of the supplies is gone, the application is closing.
+    Closed,
+}
+
+impl std::fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceError::UnknownSupply(supply) => write!(f, "Unknown power supply {supply}"),
+            SequenceError::UnknownChannel { supply, channel } => {
+                write!(f, "Unknown channel {channel} of {supply}")
+            }
+            SequenceError::Send(err) => write!(f, "{err}"),
+            SequenceError::Timeout { supply, channel } => {
+                write!(
+                    f,
+                    "Voltage of {supply} {channel} did not reach its setpoint"
+                )
+            }
+            SequenceError::Closed => write!(f, "Telemetry closed"),
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+/// Resolves the names of a step into the supply and channel index.
+fn resolve(
+    supplies: &Supplies,
+    supply: &str,
+    channel: &str,
+) -> Result<(SupplyState, u8), SequenceError> {
+    let state = supplies
+        .find(supply)
+        .ok_or_else(|| SequenceError::UnknownSupply(supply.to_string()))?;
+    let index = state
+        .find_channel(channel)
+        .ok_or_else(|| SequenceError::UnknownChannel {
+            supply: supply.to_string(),
+            channel: channel.to_string(),
+        })?;
+    Ok((state, index))
+}
+
+fn within_tolerance(channel: &ChannelState, tolerance: f32) -> bool {
+    let allowed = channel.voltage_set.abs() * tolerance / 100.0;
+    channel.enabled && (channel.voltage - channel.voltage_set).abs() <= allowed
+}
+
+/// Channels switched on by a running sequence, switched off in reverse order
+/// when the sequence fails or is cancelled.
+struct PowerDown<'a> {
+    supplies: &'a Supplies,
+    channels: Vec<(String, u8)>,
+}
+
+impl Drop for PowerDown<'_> {
+    fn drop(&mut self) {
+        for (id, index) in self.channels.drain(..).rev() {
+            info!(
+                "Sequence aborted, switching off channel {} of {id}",
+                index + 1
+            );
+            let action = PowerSupplyAction::Off(ChannelSelection::Channel(index), Instant::now());
+            if let Err(err) = self.supplies.send(&id, action) {
+                error!("Failed to switch off channel {} of {id}: {err}", index + 1);
+            }
+        }
+    }
+}
+
+/// Waits for readings of a poll started after `since` reaching the setpoint.
+async fn wait_voltage(
+    supplies: &Supplies,
+    id: &str,
+    index: u8,
+    tolerance: f32,
+    timeout: Duration,
+    since: Instant,
+) -> Result<bool, SequenceError> {
+    let mut events = supplies.subscribe();
+    let reached = |channels: &[ChannelState]| {
+        channels
+            .get(index as usize)
+            .is_some_and(|channel| within_tolerance(channel, tolerance))
+    };
+    // polls started before the previous steps were handled do not reflect them
+    let wait = async {
+        loop {
+            match events.recv().await {
+                Ok(frame) if frame.supply == id => {
+                    if let Event::Readings {
+                        channels,
+                        started: Some(started),
+                    } = &frame.event
+                        && *started > since
+                        && reached(channels)
+                    {
+                        return Ok(true);
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(SequenceError::Closed),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .unwrap_or(Ok(false))
+}
+
+/// Runs the steps, reporting the index of each step before it starts.
+///
+/// Channels switched on by the sequence are switched off in reverse order
+/// when a step fails or the returned future is dropped before it completes.
+pub async fn run(
+    supplies: &Supplies,
+    steps: &[SequenceStep],
+    mut progress: impl FnMut(usize),
+) -> Result<(), SequenceError> {
+    let mut power_down = PowerDown {
+        supplies,
+        channels: Vec::new(),
+    };
+    // time the last action of the sequence was queued
+    let mut sent = Instant::now();
+
+    for (number, step) in steps.iter().enumerate() {
+        progress(number);
+        match step {
+            SequenceStep::On { supply, channel } => {
+                let (state, index) = resolve(supplies, supply, channel)?;
+                let action = PowerSupplyAction::On(ChannelSelection::Channel(index));
+                supplies
+                    .send(&state.id, action)
+                    .map_err(SequenceError::Send)?;
+                sent = Instant::now();
+                // channels already on are left on when the sequence is aborted
+                let was_on = state
+                    .channels
+                    .get(index as usize)
+                    .is_some_and(|channel| channel.enabled);
+                if !was_on {
+                    power_down.channels.push((state.id, index));
+                }
+            }
+            SequenceStep::Off { supply, channel } => {
+                let (SupplyState { id, .. }, index) = resolve(supplies, supply, channel)?;
+                let action =
+                    PowerSupplyAction::Off(ChannelSelection::Channel(index), Instant::now());
+                supplies.send(&id, action).map_err(SequenceError::Send)?;
+                sent = Instant::now();
+                power_down
+                    .channels
+                    .retain(|(on_id, on_index)| *on_id != id || *on_index != index);
+            }
+            SequenceStep::WaitVoltage {
+                supply,
+                channel,
+                tolerance,
+                timeout_ms,
+            } => {
+                let (SupplyState { id, .. }, index) = resolve(supplies, supply, channel)?;
+                let timeout = Duration::from_millis(*timeout_ms);
+                if !wait_voltage(supplies, &id, index, *tolerance, timeout, sent).await? {
+                    return Err(SequenceError::Timeout {
+                        supply: supply.clone(),
+                        channel: channel.clone(),
+                    });
+                }
+            }
+            SequenceStep::Delay { ms } => {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+            }
+        }
+    }
+
+    power_down.channels.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::{StreamExt, channel::mpsc::unbounded};
+
+    use super::*;
+    use crate::{supplies::SupplyState, test_support};
+
+    /// Supply reaching the setpoint on all channels except `fan`.
+    fn supply() -> SupplyState {
+        let mut supply = test_support::supply(&["psu", "fan"]);
+        for channel in &mut supply.channels {
+            channel.voltage_set = 12.0;
+            channel.current_set = 1.0;
+        }
+        supply
+    }
+
+    /// Registers the supply with the channels switched on, returning the log of the actions
+    /// it received.
+    fn register(supplies: &Supplies, on: &[&str]) -> Arc<Mutex<Vec<String>>> {
+        let mut state = supply();
+        for channel in &mut state.channels {
+            if on.contains(&channel.name.as_str()) {
+                channel.enabled = true;
+                channel.voltage = 11.7;
+            }
+        }
+        let (tx, mut rx) = unbounded();
+        supplies.register(state.clone(), tx);
+        let actions = Arc::new(Mutex::new(Vec::new()));
+
+        let log = actions.clone();
+        let supplies = supplies.clone();
+        let registered = Instant::now();
+        tokio::spawn(async move {
+            while let Some(action) = rx.next().await {
+                // readings of a poll started before the action
+                let event = Event::Readings {
+                    channels: state.channels.clone(),
+                    started: Some(registered),
+                };
+                supplies.publish("SIM", "bench", event);
+
+                let (index, on) = match action {
+                    PowerSupplyAction::On(ChannelSelection::Channel(index)) => (index, true),
+                    PowerSupplyAction::Off(ChannelSelection::Channel(index), _) => (index, false),
+                    _ => continue,
+                };
+                let channel = &mut state.channels[index as usize];
+                log.lock().unwrap().push(format!(
+                    "{} {}",
+                    channel.name,
+                    if on { "on" } else { "off" }
+                ));
+                channel.enabled = on;
+                channel.voltage = match (on, channel.name.as_str()) {
+                    (true, "fan") => 3.0,
+                    (true, _) => 11.7,
+                    (false, _) => 0.0,
+                };
+                let event = Event::Readings {
+                    channels: state.channels.clone(),
+                    started: Some(Instant::now()),
+                };
+                supplies.publish("SIM", "bench", event);
+                supplies.update(state.clone());
+            }
+        });
+        actions
+    }
+
+    fn step(step: &str, channel: &str) -> SequenceStep {
+        let json = format!(
+            r#"{{"step": "{step}", "supply": "bench", "channel": "{channel}", "timeout_ms": 200}}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sequence() {
+        let supplies = Supplies::default();
+        let actions = register(&supplies, &[]);
+
+        let steps = [
+            step("on", "psu"),
+            step("wait_voltage", "psu"),
+            SequenceStep::Delay { ms: 10 },
+            step("on", "2"),
+            step("off", "fan"),
+        ];
+        let mut started = Vec::new();
+        run(&supplies, &steps, |step| started.push(step))
+            .await
+            .unwrap();
+        assert_eq!(started, [0, 1, 2, 3, 4]);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(*actions.lock().unwrap(), ["psu on", "fan on", "fan off"]);
+    }
+
+    #[tokio::test]
+    async fn test_sequence_abort() {
+        let supplies = Supplies::default();
+        let actions = register(&supplies, &[]);
+
+        let steps = [
+            step("on", "psu"),
+            step("on", "fan"),
+            step("wait_voltage", "fan"),
+            step("on", "psu"),
+        ];
+        let err = run(&supplies, &steps, |_| {}).await.unwrap_err();
+        assert_eq!(
+            err,
+            SequenceError::Timeout {
+                supply: "bench".to_string(),
+                channel: "fan".to_string(),
+            }
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // powered down in reverse order
+        assert_eq!(
+            *actions.lock().unwrap(),
+            ["psu on", "fan on", "fan off", "psu off"]
+        );
+
+        let err = run(&supplies, &[step("on", "dut")], |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Unknown channel dut of bench");
+    }
+
+    #[tokio::test]
+    async fn test_sequence_channel_on() {
+        let supplies = Supplies::default();
+        let actions = register(&supplies, &["psu"]);
+
+        // psu was on before the sequence, it is left on
+        let steps = [
+            step("on", "psu"),
+            step("on", "fan"),
+            step("wait_voltage", "fan"),
+        ];
+        run(&supplies, &steps, |_| {}).await.unwrap_err();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(*actions.lock().unwrap(), ["psu on", "fan on", "fan off"]);
+
+        // psu at its setpoint in a poll started before the off does not count
+        let steps = [step("off", "psu"), step("wait_voltage", "psu")];
+        assert!(matches!(
+            run(&supplies, &steps, |_| {}).await,
+            Err(SequenceError::Timeout { .. })
+        ));
+    }
+}
@@ -0,0 +1,949 @@
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    sync::{Arc, LazyLock, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use bytes::{Buf, BytesMut};
+use log::trace;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    models::{Features, Model, VRange},
+    mx100qp::{EXECUTION_ERROR_EMPTY_STORE, EXECUTION_ERROR_RANGE, EventStatus, LimitEventStatus},
+};
+
+/// Error raised by a command, reported through the event status register.
+enum Error {
+    /// Command was not recognized.
+    Command,
+    /// Command could not be executed, holds the execution error register code.
+    Execution(u8),
+}
+
+type Reply = Result<Option<String>, Error>;
+
+fn parse<T: std::str::FromStr>(arg: &str) -> Result<T, Error> {
+    arg.parse().map_err(|_| Error::Command)
+}
+
+/// Behaviour of a channel on `OPALL`, set by `ONACTION` and `OFFACTION`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MultiAction {
+    Quick,
+    Never,
+    Delay,
+}
+
+impl MultiAction {
+    fn parse(arg: &str) -> Result<Self, Error> {
+        match arg {
+            "QUICK" => Ok(MultiAction::Quick),
+            "NEVER" => Ok(MultiAction::Never),
+            "DELAY" => Ok(MultiAction::Delay),
+            _ => Err(Error::Execution(EXECUTION_ERROR_RANGE)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            MultiAction::Quick => "QUICK",
+            MultiAction::Never => "NEVER",
+            MultiAction::Delay => "DELAY",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SimulatedChannel {
+    voltage: f32,
+    current: f32,
+    enabled: bool,
+    vrange: u8,
+    overvoltage_trip: Option<f32>,
+    overcurrent_trip: Option<f32>,
+    ovp_threshold: f32,
+    ocp_threshold: f32,
+    on_action: MultiAction,
+    on_delay_ms: u32,
+    on_at: Option<Instant>,
+    off_action: MultiAction,
+    off_delay_ms: u32,
+    off_at: Option<Instant>,
+    /// Latched limit events, cleared by reading `LSR<N>?`.
+    limit_status: LimitEventStatus,
+    /// Latched trips, cleared only by `TRIPRST`.
+    tripped: LimitEventStatus,
+    load_ohms: f32,
+}
+
+impl SimulatedChannel {
+    /// Restores settings from a setup store, output state and load are kept.
+    fn recall(&mut self, setup: &SimulatedChannel) {
+        *self = SimulatedChannel {
+            enabled: self.enabled,
+            on_at: self.on_at,
+            off_at: self.off_at,
+            limit_status: self.limit_status,
+            tripped: self.tripped,
+            load_ohms: self.load_ohms,
+            ..setup.clone()
+        };
+    }
+}
+
+impl SimulatedChannel {
+    fn new(vrange: u8) -> Self {
+        Self {
+            voltage: 0.0,
+            current: 0.0,
+            enabled: false,
+            vrange,
+            overvoltage_trip: None,
+            overcurrent_trip: None,
+            ovp_threshold: 40.0,
+            ocp_threshold: 7.0,
+            on_action: MultiAction::Quick,
+            on_delay_ms: 0,
+            on_at: None,
+            off_action: MultiAction::Quick,
+            off_delay_ms: 0,
+            off_at: None,
+            limit_status: LimitEventStatus::empty(),
+            tripped: LimitEventStatus::empty(),
+            load_ohms: 100.0,
+        }
+    }
+}
+
+/// In-process supply of one of the [`crate::models`] speaking its command set.
+///
+/// Implements [`AsyncRead`] and [`AsyncWrite`], so it can be used as a transport for
+/// [`crate::mx100qp::Mx100qp`]. Every line written is processed immediately and the
+/// replies become readable.
+pub struct Simulator {
+    model: &'static Model,
+    serial_number: String,
+    channels: Vec<SimulatedChannel>,
+    voltage_tracking: u8,
+    event_status: EventStatus,
+    execution_error: u8,
+    stores: HashMap<u8, (Vec<SimulatedChannel>, u8)>,
+    channel_stores: HashMap<(usize, u8), SimulatedChannel>,
+    input: BytesMut,
+    output: BytesMut,
+    read_waker: Option<Waker>,
+}
+
+impl Simulator {
+    pub fn new(model: &'static Model, serial_number: &str) -> Self {
+        let channels = (0..model.channels)
+            .map(|ch| {
+                let vrange = model.selectable_ranges(ch).next().map_or(0, |(i, _)| i);
+                SimulatedChannel::new(vrange)
+            })
+            .collect();
+        Self {
+            model,
+            serial_number: serial_number.to_string(),
+            channels,
+            voltage_tracking: model.default_tracking(),
+            event_status: EventStatus::empty(),
+            execution_error: 0,
+            stores: HashMap::new(),
+            channel_stores: HashMap::new(),
+            input: BytesMut::new(),
+            output: BytesMut::new(),
+            read_waker: None,
+        }
+    }
+
+    /// Resistance of the load connected to the channel output.
+    pub fn set_load(&mut self, ch: u8, ohms: f32) {
+        self.channels[ch as usize].load_ohms = ohms;
+    }
+
+    fn master(&self, ch: usize) -> usize {
+        let pairs = self
+            .model
+            .tracking_mode(self.voltage_tracking)
+            .map(|mode| mode.pairs)
+            .unwrap_or_default();
+        pairs
+            .iter()
+            .find(|(_, slave)| *slave as usize == ch)
+            .map_or(ch, |(master, _)| *master as usize)
+    }
+
+    fn is_slave(&self, ch: usize) -> bool {
+        self.master(ch) != ch
+    }
+
+    fn channel_on(&mut self, ch: usize) {
+        let channel = &mut self.channels[ch];
+        channel.on_at = None;
+        channel.off_at = None;
+        if channel.tripped.is_empty() {
+            channel.enabled = true;
+        }
+    }
+
+    fn enabled(&self, ch: usize) -> bool {
+        self.channels[self.master(ch)].enabled
+    }
+
+    /// Returns output voltage and current of the channel driving its load.
+    fn readback(&self, ch: usize) -> (f32, f32) {
+        if !self.enabled(ch) {
+            return (0.0, 0.0);
+        }
+
+        let voltage = self.channels[self.master(ch)].voltage;
+        let channel = &self.channels[ch];
+        let current = voltage / channel.load_ohms;
+        if current > channel.current {
+            (channel.current * channel.load_ohms, channel.current)
+        } else {
+            (voltage, current)
+        }
+    }
+
+    /// Advances delayed multi-on and multi-off and evaluates limits and trips.
+    fn update(&mut self) {
+        let now = Instant::now();
+        for ch in 0..self.channels.len() {
+            if let Some(on_at) = self.channels[ch].on_at
+                && now >= on_at
+            {
+                self.channel_on(ch);
+            }
+            let channel = &mut self.channels[ch];
+            if let Some(off_at) = channel.off_at
+                && now >= off_at
+            {
+                channel.enabled = false;
+                channel.off_at = None;
+            }
+        }
+
+        for ch in 0..self.channels.len() {
+            if !self.enabled(ch) {
+                continue;
+            }
+
+            let (voltage, current) = self.readback(ch);
+            let master = self.master(ch);
+            let channel = &mut self.channels[ch];
+            if current >= channel.current {
+                channel.limit_status |= LimitEventStatus::CURRENT_LIMIT;
+            }
+
+            let mut trip = LimitEventStatus::empty();
+            if channel.overvoltage_trip.is_some_and(|ovp| voltage >= ovp) {
+                trip |= LimitEventStatus::OVER_VOLTAGE_TRIP;
+            }
+            if channel.overcurrent_trip.is_some_and(|ocp| current >= ocp) {
+                trip |= LimitEventStatus::OVER_CURRENT_TRIP;
+            }
+            if !trip.is_empty() {
+                channel.tripped |= trip;
+                channel.limit_status |= trip;
+                self.channels[master].enabled = false;
+            }
+        }
+    }
+
+    fn range(&self, ch: usize) -> &'static VRange {
+        &self.model.ranges[ch][self.channels[ch].vrange as usize]
+    }
+
+    fn set_voltage(&mut self, ch: usize, voltage: f32) -> Result<(), Error> {
+        if !(0.0..=self.range(ch).voltage).contains(&voltage) {
+            return Err(Error::Execution(EXECUTION_ERROR_RANGE));
+        }
+        if !self.is_slave(ch) {
+            self.channels[ch].voltage = voltage;
+        }
+        Ok(())
+    }
+
+    fn set_current(&mut self, ch: usize, current: f32) -> Result<(), Error> {
+        if !(0.0..=self.range(ch).current).contains(&current) {
+            return Err(Error::Execution(EXECUTION_ERROR_RANGE));
+        }
+        self.channels[ch].current = current;
+        Ok(())
+    }
+
+    fn set_vrange(&mut self, ch: usize, vrange: u8) -> Result<(), Error> {
+        let Some((_, range)) = self
+            .model
+            .selectable_ranges(ch as u8)
+            .find(|(i, _)| *i == vrange)
+        else {
+            return Err(Error::Execution(EXECUTION_ERROR_RANGE));
+        };
+        let channel = &mut self.channels[ch];
+        channel.vrange = vrange;
+        channel.voltage = channel.voltage.min(range.voltage);
+        channel.current = channel.current.min(range.current);
+        Ok(())
+    }
+
+    fn set_trip(&mut self, ch: usize, unit: char, arg: &str) -> Result<(), Error> {
+        let channel = &mut self.channels[ch];
+        let (trip, threshold) = match unit {
+            'V' => (&mut channel.overvoltage_trip, &mut channel.ovp_threshold),
+            _ => (&mut channel.overcurrent_trip, &mut channel.ocp_threshold),
+        };
+        match arg {
+            "ON" => *trip = Some(*threshold),
+            "OFF" => *trip = None,
+            value => {
+                *threshold = parse(value)?;
+                if trip.is_some() {
+                    *trip = Some(*threshold);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn multi_on(&mut self) {
+        let now = Instant::now();
+        for ch in 0..self.channels.len() {
+            let channel = &mut self.channels[ch];
+            match channel.on_action {
+                MultiAction::Quick => self.channel_on(ch),
+                MultiAction::Delay => {
+                    channel.on_at = Some(now + Duration::from_millis(channel.on_delay_ms as u64))
+                }
+                MultiAction::Never => {}
+            }
+        }
+    }
+
+    fn multi_off(&mut self) {
+        let now = Instant::now();
+        for channel in self.channels.iter_mut() {
+            match channel.off_action {
+                MultiAction::Quick => {
+                    channel.enabled = false;
+                    channel.on_at = None;
+                }
+                MultiAction::Delay => {
+                    channel.on_at = None;
+                    channel.off_at = Some(now + Duration::from_millis(channel.off_delay_ms as u64));
+                }
+                MultiAction::Never => {}
+            }
+        }
+    }
+
+    /// Processes single command line and returns the reply, if the command has any.
+    pub fn process(&mut self, line: &str) -> Option<String> {
+        self.update();
+
+        let line = line.trim().to_uppercase();
+        let (header, arg) = match line.split_once(' ') {
+            Some((header, arg)) => (header, arg.trim()),
+            None => (line.as_str(), ""),
+        };
+        let (header, query) = match header.strip_suffix('?') {
+            Some(header) => (header, true),
+            None => (header, false),
+        };
+
+        let reply = match self.process_common(header, query, arg) {
+            Err(Error::Command) => self.process_channel(header, query, arg),
+            reply => reply,
+        };
+
+        self.update();
+        match reply {
+            Ok(reply) => reply,
+            Err(Error::Command) => {
+                self.event_status |= EventStatus::COMMAND_ERROR;
+                None
+            }
+            Err(Error::Execution(code)) => {
+                self.event_status |= EventStatus::EXECUTION_ERROR;
+                self.execution_error = code;
+                None
+            }
+        }
+    }
+
+    fn process_common(&mut self, header: &str, query: bool, arg: &str) -> Reply {
+        match (header, query) {
+            ("*IDN", true) => Ok(Some(format!(
+                "THURLBY THANDAR, {}, {}, 1.00-1.00-1.00",
+                self.model.name, self.serial_number
+            ))),
+            ("*ESR", true) => {
+                let status = std::mem::replace(&mut self.event_status, EventStatus::empty());
+                Ok(Some(status.bits().to_string()))
+            }
+            ("*STB", true) => {
+                let summary = if self.event_status.is_empty() {
+                    0
+                } else {
+                    1 << 5
+                };
+                Ok(Some(summary.to_string()))
+            }
+            ("EER", true) => Ok(Some(std::mem::take(&mut self.execution_error).to_string())),
+            ("QER", true) => Ok(Some("0".to_string())),
+            ("OPALL", false) if self.model.supports(Features::ALL_OUTPUTS) => {
+                match arg {
+                    "1" => self.multi_on(),
+                    "0" => self.multi_off(),
+                    _ => return Err(Error::Execution(EXECUTION_ERROR_RANGE)),
+                }
+                Ok(None)
+            }
+            ("CONFIG", true) if !self.model.tracking.is_empty() => {
+                Ok(Some(self.voltage_tracking.to_string()))
+            }
+            ("CONFIG", false) if !self.model.tracking.is_empty() => {
+                let config = parse(arg)?;
+                if self.model.tracking_mode(config).is_none() {
+                    return Err(Error::Execution(EXECUTION_ERROR_RANGE));
+                }
+                self.voltage_tracking = config;
+                for ch in 0..self.channels.len() {
+                    if self.is_slave(ch) {
+                        self.channels[ch].enabled = false;
+                    }
+                }
+                Ok(None)
+            }
+            ("*SAV", false) if self.model.supports(Features::SETUP_STORES) => {
+                match parse(arg)? {
+                    store if store < self.model.setup_stores => self
+                        .stores
+                        .insert(store, (self.channels.clone(), self.voltage_tracking)),
+                    _ => return Err(Error::Execution(EXECUTION_ERROR_RANGE)),
+                };
+                Ok(None)
+            }
+            ("*RCL", false) if self.model.supports(Features::SETUP_STORES) => {
+                let (channels, voltage_tracking) = self
+                    .stores
+                    .get(&parse(arg)?)
+                    .ok_or(Error::Execution(EXECUTION_ERROR_EMPTY_STORE))?;
+                for (channel, setup) in self.channels.iter_mut().zip(channels) {
+                    channel.recall(setup);
+                }
+                self.voltage_tracking = *voltage_tracking;
+                Ok(None)
+            }
+            ("TRIPRST", false) => {
+                for channel in self.channels.iter_mut() {
+                    channel.tripped = LimitEventStatus::empty();
+                }
+                Ok(None)
+            }
+            _ => Err(Error::Command),
+        }
+    }
+
+    fn process_channel(&mut self, header: &str, query: bool, arg: &str) -> Reply {
+        const PREFIXES: [&str; 12] = [
+            "ONACTION",
+            "ONDELAY",
+            "OFFACTION",
+            "OFFDELAY",
+            "LSR",
+            "OVP",
+            "OCP",
+            "OP",
+            "SAV",
+            "RCL",
+            "V",
+            "I",
+        ];
+        // the range command of the model is handled as RANGE, e.g. VRANGE of MX100QP
+        let range = self
+            .model
+            .range_command
+            .and_then(|command| Some(("RANGE", header.strip_prefix(command)?)));
+        let (prefix, rest) = range
+            .or_else(|| {
+                PREFIXES
+                    .iter()
+                    .find_map(|prefix| Some((*prefix, header.strip_prefix(prefix)?)))
+            })
+            .ok_or(Error::Command)?;
+        let feature = match prefix {
+            "ONACTION" | "ONDELAY" => Features::MULTI_ON,
+            "OFFACTION" | "OFFDELAY" => Features::MULTI_OFF,
+            "SAV" | "RCL" => Features::CHANNEL_SETUP_STORES,
+            _ => Features::empty(),
+        };
+        if !self.model.supports(feature) {
+            return Err(Error::Command);
+        }
+        let digit = rest
+            .chars()
+            .next()
+            .and_then(|digit| digit.to_digit(10))
+            .ok_or(Error::Command)? as usize;
+        if !(1..=self.channels.len()).contains(&digit) {
+            return Err(Error::Command);
+        }
+        let ch = digit - 1;
+        let suffix = &rest[1..];
+
+        match (prefix, suffix, query) {
+            ("ONACTION", "", false) => {
+                let channel = &mut self.channels[ch];
+                channel.on_at = None;
+                channel.on_action = MultiAction::parse(arg)?;
+                Ok(None)
+            }
+            ("ONACTION", "", true) => Ok(Some(self.channels[ch].on_action.name().to_string())),
+            ("ONDELAY", "", false) => {
+                self.channels[ch].on_delay_ms = parse(arg)?;
+                Ok(None)
+            }
+            ("ONDELAY", "", true) => Ok(Some(format!("{}", self.channels[ch].on_delay_ms))),
+            ("OFFACTION", "", false) => {
+                let channel = &mut self.channels[ch];
+                channel.off_at = None;
+                channel.off_action = MultiAction::parse(arg)?;
+                Ok(None)
+            }
+            ("OFFACTION", "", true) => Ok(Some(self.channels[ch].off_action.name().to_string())),
+            ("OFFDELAY", "", false) => {
+                self.channels[ch].off_delay_ms = parse(arg)?;
+                Ok(None)
+            }
+            ("OFFDELAY", "", true) => Ok(Some(format!("{}", self.channels[ch].off_delay_ms))),
+            ("RANGE", "", false) => {
+                self.set_vrange(ch, parse(arg)?)?;
+                Ok(None)
+            }
+            ("RANGE", "", true) => Ok(Some(self.channels[ch].vrange.to_string())),
+            ("LSR", "", true) => {
+                let channel = &mut self.channels[ch];
+                let status = channel.limit_status | channel.tripped;
+                channel.limit_status = LimitEventStatus::empty();
+                Ok(Some(status.bits().to_string()))
+            }
+            ("OVP" | "OCP", "", _) => {
+                let unit = prefix.chars().nth(1).unwrap();
+                if !query {
+                    self.set_trip(ch, unit, arg)?;
+                    return Ok(None);
+                }
+                // e.g. MX100QP does not answer OVP2?/OCP2? when CONFIG is 3
+                if self
+                    .model
+                    .has_silent_trips(self.voltage_tracking, digit as u8)
+                {
+                    return Ok(None);
+                }
+                let channel = &self.channels[ch];
+                let trip = match unit {
+                    'V' => channel.overvoltage_trip,
+                    _ => channel.overcurrent_trip,
+                };
+                Ok(Some(match trip {
+                    Some(threshold) => format!("{unit}P{digit} {threshold:.3}"),
+                    None => format!("{unit}P{digit} OFF"),
+                }))
+            }
+            ("OP", "", false) => {
+                match arg {
+                    "1" if !self.is_slave(ch) => self.channel_on(ch),
+                    "0" if !self.is_slave(ch) => self.channels[ch].enabled = false,
+                    "1" | "0" => {}
+                    _ => return Err(Error::Execution(EXECUTION_ERROR_RANGE)),
+                }
+                Ok(None)
+            }
+            ("OP", "", true) => Ok(Some(if self.enabled(ch) { "1" } else { "0" }.to_string())),
+            ("SAV", "", false) => {
+                match parse(arg)? {
+                    store if store < self.model.setup_stores => self
+                        .channel_stores
+                        .insert((ch, store), self.channels[ch].clone()),
+                    _ => return Err(Error::Execution(EXECUTION_ERROR_RANGE)),
+                };
+                Ok(None)
+            }
+            ("RCL", "", false) => {
+                let setup = self
+                    .channel_stores
+                    .get(&(ch, parse(arg)?))
+                    .ok_or(Error::Execution(EXECUTION_ERROR_EMPTY_STORE))?;
+                self.channels[ch].recall(setup);
+                Ok(None)
+            }
+            ("V", "", false) => {
+                self.set_voltage(ch, parse(arg)?)?;
+                Ok(None)
+            }
+            ("V", "", true) => Ok(Some(format!(
+                "V{digit} {:.3}",
+                self.channels[self.master(ch)].voltage
+            ))),
+            ("V", "O", true) => Ok(Some(format!("{:.3}V", self.readback(ch).0))),
+            ("I", "", false) => {
+                self.set_current(ch, parse(arg)?)?;
+                Ok(None)
+            }
+            ("I", "", true) => Ok(Some(format!("I{digit} {:.3}", self.channels[ch].current))),
+            ("I", "O", true) => Ok(Some(format!("{:.3}A", self.readback(ch).1))),
+            _ => Err(Error::Command),
+        }
+    }
+}
+
+impl AsyncRead for Simulator {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.output.is_empty() {
+            self.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = buf.remaining().min(self.output.len());
+        buf.put_slice(&self.output[..len]);
+        self.output.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Simulator {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.input.extend_from_slice(buf);
+
+        while let Some(n) = self.input.iter().position(|b| *b == b'\n') {
+            let line = self.input.split_to(n + 1);
+            let line = String::from_utf8_lossy(&line).to_string();
+            if let Some(reply) = self.process(&line) {
+                trace!("Simulator replied {reply}");
+                self.output.extend_from_slice(reply.as_bytes());
+                self.output.extend_from_slice(b"\r\n");
+                if let Some(waker) = self.read_waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Virtual supplies by serial number, kept for the whole process so that a reconnected
+/// supply keeps its settings.
+static VIRTUAL_SUPPLIES: LazyLock<Mutex<HashMap<String, Arc<Mutex<Simulator>>>>> =
+    LazyLock::new(Default::default);
+
+/// Connection to a simulator shared by all connections to the same virtual supply.
+pub struct VirtualSupply(Arc<Mutex<Simulator>>);
+
+impl VirtualSupply {
+    /// Connects to the virtual supply of the serial number, created on the first connection
+    /// or when its model changes. Replies left unread by a previous connection are discarded.
+    pub fn connect(model: &'static Model, serial_number: &str) -> Self {
+        let simulator = VIRTUAL_SUPPLIES
+            .lock()
+            .unwrap()
+            .entry(serial_number.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(Simulator::new(model, serial_number))))
+            .clone();
+        {
+            let mut state = simulator.lock().unwrap();
+            if !std::ptr::eq(state.model, model) {
+                *state = Simulator::new(model, serial_number);
+            }
+            state.input.clear();
+            state.output.clear();
+            state.read_waker = None;
+        }
+        Self(simulator)
+    }
+}
+
+impl AsyncRead for VirtualSupply {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for VirtualSupply {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{Connection, PowerSupplyConfig},
+        models::MX100QP,
+        mx100qp::{
+            InstrumentError, MultiChannelOff, MultiChannelOn, Mx100qp, ProtocolError,
+            VoltageTrackingState,
+        },
+    };
+
+    async fn connect(simulator: Simulator) -> Mx100qp {
+        Mx100qp::with_transport(Box::new(simulator), "SIM")
+            .await
+            .unwrap()
+    }
+
+    /// Output states of all channels.
+    async fn enabled(port: &mut Mx100qp) -> Vec<bool> {
+        let channels = port.read_channels().await.unwrap();
+        channels.iter().map(|channel| channel.enabled).collect()
+    }
+
+    #[tokio::test]
+    async fn test_models() {
+        for model in crate::models::MODELS {
+            let mut port = connect(Simulator::new(model, "SIM")).await;
+            assert_eq!(port.model, *model);
+
+            let mut config: PowerSupplyConfig = serde_json::from_str(
+                r#"{"simulator": "SIM", "id": "SIM", "name": "sim", "channels": []}"#,
+            )
+            .unwrap();
+            config.adapt_to_model(model);
+            for channel in &mut config.channels {
+                channel.voltage = 5.0;
+                channel.current = 0.5;
+            }
+            port.apply_config(&config).await.unwrap();
+            port.all_channel_on().await.unwrap();
+
+            for mode in model.tracking {
+                port.set_voltage_tracking(mode.config).await.unwrap();
+                let channels = port.read_channels().await.unwrap();
+                assert_eq!(channels.len(), model.channels as usize, "{}", model.name);
+                assert_eq!(channels[0].voltage.current, 5.0, "{}", model.name);
+            }
+            for ch in 0..model.channels {
+                for (vrange, _) in model.selectable_ranges(ch).filter(|_| model.has_ranges(ch)) {
+                    port.set_vrange(ch, vrange).await.unwrap();
+                    assert_eq!(
+                        port.read_channels().await.unwrap()[ch as usize].vrange,
+                        vrange
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_virtual_supply() {
+        let connection = Connection::Simulator {
+            simulator: "VIRTUAL".to_string(),
+            simulated_model: Some("CPX400DP".to_string()),
+        };
+        let transport = crate::transport::open(&connection).await.unwrap();
+        let mut port = Mx100qp::with_transport(transport, "VIRTUAL").await.unwrap();
+        assert_eq!(port.model.name, "CPX400DP");
+        port.set_voltage(1, 12.0).await.unwrap();
+        drop(port);
+
+        // the settings survive a reconnect
+        let transport = crate::transport::open(&connection).await.unwrap();
+        let mut port = Mx100qp::with_transport(transport, "VIRTUAL").await.unwrap();
+        assert_eq!(port.read_channels().await.unwrap()[1].voltage.set, 12.0);
+    }
+
+    #[tokio::test]
+    async fn test_set_and_read() {
+        let mut port = connect(Simulator::new(&MX100QP, "SIM")).await;
+        port.set_voltage(0, 12.0).await.unwrap();
+        port.set_current(0, 1.0).await.unwrap();
+        port.channel_on(0).await.unwrap();
+
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels.len(), 4);
+        assert!(channels[0].enabled);
+        assert_eq!(channels[0].voltage.set, 12.0);
+        assert_eq!(channels[0].voltage.current, 12.0);
+        assert_eq!(channels[0].current.current, 0.12);
+        assert!(!channels[1].enabled);
+    }
+
+    #[tokio::test]
+    async fn test_range_limits() {
+        let mut port = connect(Simulator::new(&MX100QP, "SIM")).await;
+        port.set_vrange(0, 2).await.unwrap();
+        assert!(matches!(
+            port.set_voltage(0, 20.0).await,
+            Err(ProtocolError::Rejected {
+                error: InstrumentError::Execution(EXECUTION_ERROR_RANGE),
+                ..
+            })
+        ));
+        port.set_current(0, 5.0).await.unwrap();
+
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[0].vrange, 2);
+        assert_eq!(channels[0].voltage.set, 0.0);
+        assert_eq!(channels[0].current.set, 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_voltage_tracking() {
+        let mut port = connect(Simulator::new(&MX100QP, "SIM")).await;
+        port.set_voltage_tracking(3).await.unwrap();
+        port.set_voltage(0, 5.0).await.unwrap();
+        port.set_current(0, 1.0).await.unwrap();
+        port.set_current(1, 1.0).await.unwrap();
+        port.channel_on(0).await.unwrap();
+
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[0].voltage_tracking, VoltageTrackingState::Master);
+        assert_eq!(channels[1].voltage_tracking, VoltageTrackingState::Slave);
+        assert!(channels[1].enabled);
+        assert_eq!(channels[1].voltage.set, 5.0);
+        assert_eq!(channels[1].voltage.current, 5.0);
+        assert!(!channels[2].enabled);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delayed_multi_on() {
+        let mut port = connect(Simulator::new(&MX100QP, "SIM")).await;
+        port.multichannel_on_setup(0, MultiChannelOn::Delay(0))
+            .await
+            .unwrap();
+        port.multichannel_on_setup(1, MultiChannelOn::Delay(500))
+            .await
+            .unwrap();
+        port.multichannel_on_setup(2, MultiChannelOn::Disabled)
+            .await
+            .unwrap();
+        port.all_channel_on().await.unwrap();
+
+        assert_eq!(enabled(&mut port).await, [true, false, false, true]);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(enabled(&mut port).await, [true, true, false, true]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delayed_multi_off() {
+        let mut port = connect(Simulator::new(&MX100QP, "SIM")).await;
+        port.multichannel_off_setup(0, MultiChannelOff::Delay(500))
+            .await
+            .unwrap();
+        port.multichannel_off_setup(1, MultiChannelOff::Disabled)
+            .await
+            .unwrap();
+        port.all_channel_on().await.unwrap();
+        port.all_channel_off().await.unwrap();
+
+        assert_eq!(enabled(&mut port).await, [true, true, false, false]);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(enabled(&mut port).await, [false, true, false, false]);
+    }
+
+    #[tokio::test]
+    async fn test_trip_latching() {
+        let mut simulator = Simulator::new(&MX100QP, "SIM");
+        simulator.set_load(0, 10.0);
+        let mut port = connect(simulator).await;
+        port.set_voltage(0, 10.0).await.unwrap();
+        port.set_current(0, 2.0).await.unwrap();
+        port.set_overcurrent_trip(0, Some(0.5)).await.unwrap();
+        port.channel_on(0).await.unwrap();
+
+        let channels = port.read_channels().await.unwrap();
+        assert!(!channels[0].enabled);
+        assert!(
+            channels[0]
+                .status
+                .contains(LimitEventStatus::OVER_CURRENT_TRIP)
+        );
+
+        port.channel_on(0).await.unwrap();
+        assert!(!port.read_channels().await.unwrap()[0].enabled);
+
+        port.set_overcurrent_trip(0, None).await.unwrap();
+        port.trip_reset().await.unwrap();
+        port.channel_on(0).await.unwrap();
+        let channels = port.read_channels().await.unwrap();
+        assert!(channels[0].enabled);
+        assert!(
+            !channels[0]
+                .status
+                .contains(LimitEventStatus::OVER_CURRENT_TRIP)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_setup_stores() {
+        let mut port = connect(Simulator::new(&MX100QP, "SIM")).await;
+        port.set_voltage(0, 5.0).await.unwrap();
+        port.set_voltage(1, 12.0).await.unwrap();
+        port.save_setup(3).await.unwrap();
+        port.save_channel_setup(1, 7).await.unwrap();
+
+        port.set_voltage(0, 1.0).await.unwrap();
+        port.set_voltage(1, 2.0).await.unwrap();
+        port.recall_channel_setup(1, 7).await.unwrap();
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[0].voltage.set, 1.0);
+        assert_eq!(channels[1].voltage.set, 12.0);
+
+        port.set_voltage(1, 2.0).await.unwrap();
+        assert!(matches!(
+            port.recall_setup(4).await,
+            Err(ProtocolError::Rejected {
+                error: InstrumentError::Execution(EXECUTION_ERROR_EMPTY_STORE),
+                ..
+            })
+        ));
+        port.recall_setup(3).await.unwrap();
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[0].voltage.set, 5.0);
+        assert_eq!(channels[1].voltage.set, 12.0);
+    }
+}
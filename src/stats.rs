@@ -0,0 +1,153 @@
+//! Minimum, maximum, mean and RMS of the channel readbacks since the last reset.
+
+use std::time::Instant;
+
+use power_supply_control::mx100qp::Channel;
+use serde::Serialize;
+
+use crate::history::Recorder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Accumulator {
+    min: f32,
+    max: f32,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Accumulator {
+    fn new(value: f32) -> Self {
+        let value64 = value as f64;
+        Self {
+            min: value,
+            max: value,
+            sum: value64,
+            sum_squares: value64 * value64,
+        }
+    }
+
+    fn add(&mut self, value: f32) {
+        let value64 = value as f64;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value64;
+        self.sum_squares += value64 * value64;
+    }
+
+    fn summary(&self, samples: u64) -> Summary {
+        let samples = samples as f64;
+        Summary {
+            min: self.min,
+            max: self.max,
+            mean: (self.sum / samples) as f32,
+            rms: (self.sum_squares / samples).sqrt() as f32,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub rms: f32,
+}
+
+/// Statistics of a channel exported with its readings.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct StatsSummary {
+    /// Number of polls the statistics were computed from.
+    pub samples: u64,
+    pub voltage: Summary,
+    pub current: Summary,
+}
+
+/// Readbacks of a channel accumulated while its output is on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelStats {
+    samples: u64,
+    values: Option<(Accumulator, Accumulator)>,
+    /// When the current peak was last raised.
+    pub peak_at: Option<Instant>,
+}
+
+impl Recorder for ChannelStats {
+    /// Adds readings of a poll, an output that is off is not sampled.
+    fn record(&mut self, time: Instant, channel: &Channel) {
+        if !channel.enabled {
+            return;
+        }
+        let (voltage, current) = (channel.voltage.current, channel.current.current);
+        self.samples += 1;
+        match &mut self.values {
+            Some((voltages, currents)) => {
+                voltages.add(voltage);
+                if current > currents.max {
+                    self.peak_at = Some(time);
+                }
+                currents.add(current);
+            }
+            None => {
+                self.values = Some((Accumulator::new(voltage), Accumulator::new(current)));
+                self.peak_at = Some(time);
+            }
+        }
+    }
+}
+
+impl ChannelStats {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Statistics of the samples so far, `None` before the first one.
+    pub fn summary(&self) -> Option<StatsSummary> {
+        self.values.map(|(voltages, currents)| StatsSummary {
+            samples: self.samples,
+            voltage: voltages.summary(self.samples),
+            current: currents.summary(self.samples),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_support::channel;
+
+    #[test]
+    fn test_stats() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut stats = ChannelStats::default();
+        assert_eq!(stats.summary(), None);
+
+        stats.record(start, &channel(false, 0.0, 0.0));
+        stats.record(start + second, &channel(true, 5.0, 1.0));
+        stats.record(start + second * 2, &channel(true, 4.0, 7.0));
+        stats.record(start + second * 3, &channel(true, 6.0, 1.0));
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.samples, 3);
+        assert_eq!(
+            summary.voltage,
+            Summary {
+                min: 4.0,
+                max: 6.0,
+                mean: 5.0,
+                rms: (77.0f32 / 3.0).sqrt(),
+            }
+        );
+        assert_eq!(summary.current.max, 7.0);
+        assert_eq!(summary.current.mean, 3.0);
+        assert_eq!(summary.current.rms, 17.0f32.sqrt());
+        // the peak is held
+        assert_eq!(stats.peak_at, Some(start + second * 2));
+
+        stats.reset();
+        assert_eq!(stats.summary(), None);
+        assert_eq!(stats.peak_at, None);
+    }
+}
@@ -0,0 +1,237 @@
+//! Registry of the supplies shown in the GUI, shared with the API, MQTT and passthrough
+//! servers, the sequencer and the data log.
+
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::channel::mpsc::UnboundedSender;
+use power_supply_control::mx100qp::{Channel, VoltageTrackingState};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{components::power_supply::PowerSupplyAction, stats::StatsSummary};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChannelState {
+    /// Channel number starting from 1.
+    pub channel: u8,
+    pub name: String,
+    pub enabled: bool,
+    pub voltage: f32,
+    pub voltage_set: f32,
+    pub current: f32,
+    pub current_set: f32,
+    pub vrange: u8,
+    pub ovp: Option<f32>,
+    pub ocp: Option<f32>,
+    pub tracking: &'static str,
+    /// Active limits and trips.
+    pub status: Vec<&'static str>,
+    /// Statistics since their last reset in the GUI.
+    pub stats: Option<StatsSummary>,
+}
+
+impl ChannelState {
+    pub fn new(channel: &Channel, name: &str, stats: Option<StatsSummary>) -> Self {
+        Self {
+            channel: channel.index + 1,
+            name: name.to_string(),
+            enabled: channel.enabled,
+            voltage: channel.voltage.current,
+            voltage_set: channel.voltage.set,
+            current: channel.current.current,
+            current_set: channel.current.set,
+            vrange: channel.vrange,
+            ovp: channel.overvoltage_trip,
+            ocp: channel.overcurrent_trip,
+            tracking: match channel.voltage_tracking {
+                VoltageTrackingState::None => "none",
+                VoltageTrackingState::Master => "master",
+                VoltageTrackingState::Slave => "slave",
+            },
+            status: channel.status.iter_names().map(|(name, _)| name).collect(),
+            stats,
+        }
+    }
+}
+
+/// Snapshot of a supply published by its component.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SupplyState {
+    pub id: String,
+    pub name: String,
+    pub model: Option<&'static str>,
+    pub connected: bool,
+    pub voltage_tracking: Option<u8>,
+    pub error: Option<String>,
+    /// Most recent commands rejected by the device.
+    pub rejected: Vec<String>,
+    /// Duration of the last poll in seconds.
+    pub poll_duration: Option<f64>,
+    pub channels: Vec<ChannelState>,
+}
+
+impl SupplyState {
+    /// Finds the channel index by its name or number starting from 1.
+    pub fn find_channel(&self, name: &str) -> Option<u8> {
+        self.channels
+            .iter()
+            .find(|ch| ch.name == name)
+            .or_else(|| {
+                self.channels
+                    .iter()
+                    .find(|ch| ch.channel.to_string() == name)
+            })
+            .map(|ch| ch.channel - 1)
+    }
+}
+
+/// Telemetry event of a supply.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Connected {
+        model: &'static str,
+    },
+    Disconnected {
+        error: Option<String>,
+    },
+    /// New readings of all channels, sent after every poll.
+    Readings {
+        channels: Vec<ChannelState>,
+        /// Start of the poll, before which all queued actions were handled.
+        #[serde(skip)]
+        started: Option<Instant>,
+    },
+    /// Trip newly reported by a channel.
+    Trip {
+        channel: u8,
+        channel_name: String,
+        trip: &'static str,
+    },
+}
+
+/// Timestamped event sent to telemetry subscribers.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Seconds since the Unix epoch.
+    pub timestamp: f64,
+    /// Id of the supply.
+    pub supply: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl Frame {
+    pub(crate) fn new(id: &str, name: &str, event: Event) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        Self {
+            timestamp,
+            supply: id.to_string(),
+            name: name.to_string(),
+            event,
+        }
+    }
+}
+
+/// Number of frames kept for slow telemetry subscribers.
+const TELEMETRY_CAPACITY: usize = 256;
+
+pub(crate) struct Supply {
+    pub(crate) state: SupplyState,
+    pub(crate) actions: UnboundedSender<PowerSupplyAction>,
+}
+
+/// Supplies shown in the GUI, shared with the servers and the sequencer.
+#[derive(Clone)]
+pub struct Supplies {
+    supplies: Arc<Mutex<Vec<Supply>>>,
+    events: broadcast::Sender<Frame>,
+}
+
+impl Default for Supplies {
+    fn default() -> Self {
+        Self {
+            supplies: Arc::default(),
+            events: broadcast::channel(TELEMETRY_CAPACITY).0,
+        }
+    }
+}
+
+impl Supplies {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Vec<Supply>> {
+        self.supplies.lock().unwrap()
+    }
+
+    pub fn register(&self, state: SupplyState, actions: UnboundedSender<PowerSupplyAction>) {
+        let mut supplies = self.lock();
+        supplies.retain(|supply| supply.state.id != state.id);
+        supplies.push(Supply { state, actions });
+    }
+
+    pub fn unregister(&self, id: &str) {
+        self.lock().retain(|supply| supply.state.id != id);
+    }
+
+    #[cfg(any(feature = "api", feature = "mqtt"))]
+    pub fn states(&self) -> Vec<SupplyState> {
+        self.lock()
+            .iter()
+            .map(|supply| supply.state.clone())
+            .collect()
+    }
+
+    /// Finds the supply by its name or id.
+    pub fn find(&self, name: &str) -> Option<SupplyState> {
+        find_supply(&self.lock(), name).map(|supply| supply.state.clone())
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Frame> {
+        self.events.subscribe()
+    }
+
+    pub fn update(&self, state: SupplyState) {
+        let mut supplies = self.lock();
+        if let Some(supply) = supplies
+            .iter_mut()
+            .find(|supply| supply.state.id == state.id)
+        {
+            supply.state = state;
+        }
+    }
+
+    /// Queues the action into the coroutine of the connected supply.
+    pub fn send(&self, id: &str, action: PowerSupplyAction) -> Result<(), String> {
+        let supplies = self.lock();
+        let supply = supplies
+            .iter()
+            .find(|supply| supply.state.id == id)
+            .ok_or_else(|| format!("Unknown power supply {id}"))?;
+        if !supply.state.connected {
+            return Err(format!("{} is not connected", supply.state.name));
+        }
+        supply
+            .actions
+            .unbounded_send(action)
+            .map_err(|_| format!("{} is not running", supply.state.name))
+    }
+
+    /// Sends the event to telemetry subscribers, if there are any.
+    pub fn publish(&self, id: &str, name: &str, event: Event) {
+        let _ = self.events.send(Frame::new(id, name, event));
+    }
+}
+
+/// Finds the supply by its name or id.
+pub(crate) fn find_supply<'a>(supplies: &'a [Supply], name: &str) -> Option<&'a Supply> {
+    supplies
+        .iter()
+        .find(|supply| supply.state.name == name)
+        .or_else(|| supplies.iter().find(|supply| supply.state.id == name))
+}
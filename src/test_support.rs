@@ -0,0 +1,57 @@
+//! Fixtures shared by the tests of the GUI modules.
+
+use power_supply_control::mx100qp::{Channel, LimitEventStatus, Unit, VoltageTrackingState};
+
+use crate::supplies::{ChannelState, SupplyState};
+
+/// Channel with 5 V and 1 A setpoints, no trips and no active limits.
+pub(crate) fn channel(enabled: bool, voltage: f32, current: f32) -> Channel {
+    Channel {
+        index: 0,
+        vrange: 1,
+        enabled,
+        current: Unit { current, set: 1.0 },
+        voltage: Unit {
+            current: voltage,
+            set: 5.0,
+        },
+        overvoltage_trip: None,
+        overcurrent_trip: None,
+        status: LimitEventStatus::empty(),
+        voltage_tracking: VoltageTrackingState::None,
+    }
+}
+
+/// Connected supply `SIM` named `bench` with the channels switched off.
+pub(crate) fn supply(channels: &[&str]) -> SupplyState {
+    let channels = channels
+        .iter()
+        .enumerate()
+        .map(|(index, name)| ChannelState {
+            channel: index as u8 + 1,
+            name: name.to_string(),
+            enabled: false,
+            voltage: 0.0,
+            voltage_set: 0.0,
+            current: 0.0,
+            current_set: 0.0,
+            vrange: 1,
+            ovp: None,
+            ocp: None,
+            tracking: "none",
+            status: Vec::new(),
+            stats: None,
+        })
+        .collect();
+    SupplyState {
+        id: "SIM".to_string(),
+        name: "bench".to_string(),
+        model: Some("MX100QP"),
+        connected: true,
+        voltage_tracking: Some(0),
+        error: None,
+        rejected: Vec::new(),
+        poll_duration: None,
+        channels,
+    }
+}
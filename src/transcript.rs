@@ -0,0 +1,280 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+use bytes::{Buf, BytesMut};
+use log::warn;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    fn marker(self) -> char {
+        match self {
+            Direction::Sent => '>',
+            Direction::Received => '<',
+        }
+    }
+}
+
+/// Writes every line sent to and received from the device to a file.
+///
+/// Each line holds seconds since the recording started, `>` for sent or `<` for received
+/// line and the line itself. Lines starting with `#` are comments.
+///
+/// Lines are buffered and written out by [`TranscriptWriter::flush`] once per poll.
+pub struct TranscriptWriter {
+    file: BufWriter<Box<dyn Write + Send>>,
+    start: Instant,
+}
+
+impl TranscriptWriter {
+    /// Appends to the transcript file, so that reconnects do not overwrite earlier sessions.
+    pub fn create(path: &Path, header: &str) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Self::new(file, header)
+    }
+
+    /// Records to any writer, e.g. an in-memory buffer.
+    pub fn new(out: impl Write + Send + 'static, header: &str) -> io::Result<Self> {
+        let mut file = BufWriter::new(Box::new(out) as Box<dyn Write + Send>);
+        writeln!(file, "# {header}")?;
+        Ok(Self {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, direction: Direction, line: &str) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        if let Err(err) = writeln!(self.file, "{elapsed:.3} {} {line}", direction.marker()) {
+            warn!("Failed to write transcript: {err}");
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(err) = self.file.flush() {
+            warn!("Failed to write transcript: {err}");
+        }
+    }
+}
+
+/// Parses transcript into sent and received lines, ignoring timestamps.
+pub fn parse(transcript: &str) -> Result<Vec<(Direction, String)>, String> {
+    transcript
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| {
+            let mut fields = line.splitn(3, ' ');
+            let _timestamp = fields.next();
+            let direction = match fields.next() {
+                Some(">") => Direction::Sent,
+                Some("<") => Direction::Received,
+                _ => return Err(format!("line {}: missing direction", n + 1)),
+            };
+            Ok((direction, fields.next().unwrap_or_default().to_string()))
+        })
+        .collect()
+}
+
+/// Transport serving a recorded transcript back to the driver.
+///
+/// Replies are looked up by the query written, so the driver may send queries in a
+/// different order or batching than when the transcript was recorded. Every occurrence of
+/// a line in the transcript is answered once in turn, the last one is repeated afterwards.
+/// Lines never sent in the transcript fail the write.
+///
+/// Received lines are paired with the oldest query still waiting for a reply. Queries
+/// still waiting when `*IDN?` is sent were not answered by the device, they stay
+/// unanswered in the replay too, so the driver times out just like it did on the device.
+pub struct Replay {
+    replies: HashMap<String, VecDeque<Vec<String>>>,
+    input: BytesMut,
+    output: BytesMut,
+    read_waker: Option<Waker>,
+}
+
+impl Replay {
+    pub fn new(lines: Vec<(Direction, String)>) -> Self {
+        let mut exchanges: Vec<(String, Vec<String>)> = Vec::new();
+        let mut waiting = VecDeque::new();
+        for (direction, line) in lines {
+            match direction {
+                Direction::Sent => {
+                    if line == "*IDN?" {
+                        waiting.clear();
+                    }
+                    if line.contains('?') {
+                        waiting.push_back(exchanges.len());
+                    }
+                    exchanges.push((line, Vec::new()));
+                }
+                Direction::Received => match waiting.pop_front() {
+                    Some(index) => exchanges[index].1.push(line),
+                    None => warn!("Transcript reply {line:?} without a query"),
+                },
+            }
+        }
+
+        let mut replies: HashMap<String, VecDeque<Vec<String>>> = HashMap::new();
+        for (sent, received) in exchanges {
+            replies.entry(sent).or_default().push_back(received);
+        }
+        Self {
+            replies,
+            input: BytesMut::new(),
+            output: BytesMut::new(),
+            read_waker: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let lines = parse(&std::fs::read_to_string(path)?).map_err(io::Error::other)?;
+        Ok(Self::new(lines))
+    }
+
+    fn process(&mut self, line: &str) -> io::Result<()> {
+        let occurrences = self
+            .replies
+            .get_mut(line)
+            .ok_or_else(|| io::Error::other(format!("Transcript never sent {line:?}")))?;
+        let received = match occurrences.len() {
+            1 => occurrences[0].clone(),
+            _ => occurrences.pop_front().unwrap_or_default(),
+        };
+        for reply in received {
+            self.output.extend_from_slice(reply.as_bytes());
+            self.output.extend_from_slice(b"\r\n");
+        }
+        if !self.output.is_empty()
+            && let Some(waker) = self.read_waker.take()
+        {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for Replay {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.output.is_empty() {
+            self.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = buf.remaining().min(self.output.len());
+        buf.put_slice(&self.output[..len]);
+        self.output.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Replay {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.input.extend_from_slice(buf);
+
+        while let Some(n) = self.input.iter().position(|b| *b == b'\n') {
+            let line = self.input.split_to(n + 1);
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if let Err(err) = self.process(&line) {
+                return Poll::Ready(Err(err));
+            }
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mx100qp::{Mx100qp, ProtocolError, VoltageTrackingState};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    fn fixture(name: &str) -> Replay {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/transcripts")
+            .join(name);
+        Replay::load(&path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replay_config3_poll() {
+        let mut port = Mx100qp::with_transport(Box::new(fixture("config3_poll.txt")), "123")
+            .await
+            .unwrap();
+        port.set_voltage_tracking(3).await.unwrap();
+        port.set_voltage(0, 5.0).await.unwrap();
+        port.set_current(0, 1.0).await.unwrap();
+
+        let channels = port.read_channels().await.unwrap();
+        assert_eq!(channels[1].voltage_tracking, VoltageTrackingState::Slave);
+        assert_eq!(channels[1].voltage.set, 5.0);
+        assert_eq!(channels[1].overvoltage_trip, None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_diverged() {
+        let lines =
+            parse("0.000 > *IDN?\n0.010 < THURLBY THANDAR, MX100QP, 123, 1.00\n0.020 > V1 5\n")
+                .unwrap();
+        let mut port = Mx100qp::with_transport(Box::new(Replay::new(lines)), "123")
+            .await
+            .unwrap();
+        assert!(matches!(
+            port.set_voltage(0, 6.0).await,
+            Err(ProtocolError::IOError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay_by_query() {
+        let lines = parse(
+            "0.000 > V1?\n0.000 > I1?\n0.000 > V1?\n0.010 < V1 5.000\n0.010 < I1 1.000\n0.010 < V1 6.000\n",
+        )
+        .unwrap();
+        let mut replay = tokio::io::BufReader::new(Replay::new(lines));
+        let mut reply = String::new();
+        for (query, expected) in [
+            ("I1?", "I1 1.000"),
+            ("V1?", "V1 5.000"),
+            ("V1?", "V1 6.000"),
+            ("V1?", "V1 6.000"),
+        ] {
+            replay
+                .write_all(format!("{query}\n").as_bytes())
+                .await
+                .unwrap();
+            reply.clear();
+            replay.read_line(&mut reply).await.unwrap();
+            assert_eq!(reply.trim(), expected);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use log::warn;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_serial::SerialPortBuilderExt;
+
+use crate::{
+    config::Connection,
+    models::{self, MX100QP},
+    mx100qp::OpenError,
+    simulator::VirtualSupply,
+};
+
+/// Raw socket port of the MX100QP LAN interface.
+pub const LAN_PORT: u16 = 9221;
+
+/// Baud rate of the USB and RS-232 interfaces.
+pub const BAUD_RATE: u32 = 9600;
+
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+pub async fn open(connection: &Connection) -> Result<Box<dyn Transport>, OpenError> {
+    match connection {
+        Connection::Usb {
+            vid,
+            pid,
+            serial_number,
+        } => {
+            let port_path = find_usb(*vid, *pid, serial_number.as_deref())
+                .into_iter()
+                .next()
+                .ok_or(OpenError::NoDeviceFound)?;
+            open_serial(&port_path)
+        }
+        Connection::Tcp { host, port } => {
+            let stream = tokio::time::timeout(
+                Duration::from_millis(5000),
+                TcpStream::connect((host.as_str(), *port)),
+            )
+            .await??;
+            stream.set_nodelay(true)?;
+            Ok(Box::new(stream))
+        }
+        Connection::Simulator {
+            simulator,
+            simulated_model,
+        } => {
+            let model = match simulated_model {
+                Some(name) => {
+                    models::find(name).ok_or_else(|| OpenError::UnsupportedModel(name.clone()))?
+                }
+                None => &MX100QP,
+            };
+            Ok(Box::new(VirtualSupply::connect(model, simulator)))
+        }
+        Connection::Serial { serial_port } => open_serial(serial_port),
+    }
+}
+
+pub fn open_serial(port_path: &str) -> Result<Box<dyn Transport>, OpenError> {
+    Ok(Box::new(
+        tokio_serial::new(port_path, BAUD_RATE).open_native_async()?,
+    ))
+}
+
+/// Returns paths of all serial ports matching the USB identification.
+pub fn find_usb(vid: u16, pid: u16, serial_number: Option<&str>) -> Vec<String> {
+    let ports = match serialport::available_ports() {
+        Ok(ports) => ports,
+        Err(err) => {
+            warn!("Failed to list serial ports: {err}");
+            return Vec::new();
+        }
+    };
+    ports
+        .into_iter()
+        .filter_map(|p| match p.port_type {
+            serialport::SerialPortType::UsbPort(usbinfo)
+                if pid == usbinfo.pid
+                    && vid == usbinfo.vid
+                    && serial_number == usbinfo.serial_number.as_deref() =>
+            {
+                Some(p.port_name)
+            }
+            _ => None,
+        })
+        .collect()
+}